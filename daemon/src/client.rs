@@ -0,0 +1,352 @@
+use crate::daemon::SharedDaemon;
+use crate::logging::{log_error, log_info, log_warning};
+use crate::message::{DltMessage, MessageStream, DLT_SERIAL_HEADER};
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::io::{self, ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::stream::StreamExt;
+use async_std::task::JoinHandle;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Messages queued for a client before messages for it are dropped
+const DLT_DAEMON_CLIENT_QUEUE_SIZE: usize = 1000;
+
+/// Buffered messages are sent to a client in chunks of about this size
+const DLT_DAEMON_CLIENT_CHUNK_SIZE: usize = 65536;
+
+pub(crate) type ClientId = usize;
+
+struct Client {
+    id: ClientId,
+    sender: Sender<Arc<Vec<u8>>>,
+    serial_header: bool,
+    /// Messages dropped because the client did not keep up
+    dropped: u64,
+    /// Task writing the queue to the connection
+    writer: Option<JoinHandle<()>>,
+}
+
+/// The connected clients. Each client has a queue that is drained by its own
+/// writer task, so a slow client never blocks the daemon.
+#[derive(Default)]
+pub(crate) struct Clients {
+    clients: Vec<Client>,
+    next_id: ClientId,
+}
+
+impl Clients {
+    /// Add a client, returns its id and the receiving end of its queue
+    pub fn add(&mut self, serial_header: bool) -> (ClientId, Receiver<Arc<Vec<u8>>>) {
+        let (sender, receiver) = channel::bounded(DLT_DAEMON_CLIENT_QUEUE_SIZE);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.clients.push(Client {
+            id,
+            sender,
+            serial_header,
+            dropped: 0,
+            writer: None,
+        });
+        (id, receiver)
+    }
+
+    pub fn set_writer(&mut self, id: ClientId, writer: JoinHandle<()>) {
+        if let Some(client) = self.clients.iter_mut().find(|client| client.id == id) {
+            client.writer = Some(writer);
+        }
+    }
+
+    /// Remove a client, returns its writer task
+    pub fn remove(&mut self, id: ClientId) -> Option<JoinHandle<()>> {
+        let index = self.clients.iter().position(|client| client.id == id)?;
+        let client = self.clients.remove(index);
+        if client.dropped > 0 {
+            log_warning!(
+                "Client {} disconnected, {} messages dropped",
+                client.id,
+                client.dropped
+            );
+        }
+        client.writer
+    }
+
+    /// Close the queues of all clients. The writer tasks finish once the
+    /// queued messages are sent.
+    pub fn close_all(&mut self) -> Vec<JoinHandle<()>> {
+        self.clients
+            .drain(..)
+            .filter_map(|client| {
+                client.sender.close();
+                client.writer
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn with_header(client: &Client, message: &DltMessage, data: &mut Vec<u8>) {
+        if client.serial_header {
+            data.extend_from_slice(&DLT_SERIAL_HEADER);
+        }
+        data.extend_from_slice(message.as_bytes());
+    }
+
+    fn queue(client: &mut Client, data: Arc<Vec<u8>>) {
+        match client.sender.try_send(data) {
+            Ok(()) => {
+                if client.dropped > 0 {
+                    log_warning!(
+                        "Client {} caught up, {} messages dropped",
+                        client.id,
+                        client.dropped
+                    );
+                    client.dropped = 0;
+                }
+            }
+            Err(TrySendError::Full(_)) => {
+                if client.dropped == 0 {
+                    log_warning!("Client {} too slow, dropping messages", client.id);
+                }
+                client.dropped += 1;
+            }
+            // the client is going away, it removes itself
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Queue many messages for one client, batched into larger chunks so they
+    /// do not fill up the queue of the client
+    pub fn send_batch(&mut self, id: ClientId, messages: impl Iterator<Item = DltMessage>) {
+        let Some(client) = self.clients.iter_mut().find(|client| client.id == id) else {
+            return;
+        };
+        let mut chunk = Vec::new();
+        for message in messages {
+            let len = message.as_bytes().len() + DLT_SERIAL_HEADER.len();
+            if !chunk.is_empty() && chunk.len() + len > DLT_DAEMON_CLIENT_CHUNK_SIZE {
+                Self::queue(client, Arc::new(std::mem::take(&mut chunk)));
+            }
+            Self::with_header(client, &message, &mut chunk);
+        }
+        if !chunk.is_empty() {
+            Self::queue(client, Arc::new(chunk));
+        }
+    }
+
+    /// Queue a message for one client
+    pub fn send_to(&mut self, id: ClientId, message: &DltMessage) {
+        if let Some(client) = self.clients.iter_mut().find(|client| client.id == id) {
+            let mut data = Vec::new();
+            Self::with_header(client, message, &mut data);
+            Self::queue(client, Arc::new(data));
+        }
+    }
+
+    /// Queue a message for every client
+    pub fn send(&mut self, message: &DltMessage) {
+        let mut plain = None;
+        let mut with_serial_header = None;
+        for client in self.clients.iter_mut() {
+            let cache = if client.serial_header {
+                &mut with_serial_header
+            } else {
+                &mut plain
+            };
+            let data = cache
+                .get_or_insert_with(|| {
+                    let mut data = Vec::new();
+                    Self::with_header(client, message, &mut data);
+                    Arc::new(data)
+                })
+                .clone();
+            Self::queue(client, data);
+        }
+    }
+}
+
+/// Accept viewer connections
+pub(crate) async fn tcp_server(listener: TcpListener, daemon: SharedDaemon) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                async_std::task::spawn(handle_tcp_client(stream, daemon.clone()));
+            }
+            Err(e) => log_error!("Failed to accept client connection: {}", e),
+        }
+    }
+}
+
+/// Write queued messages to the client until the queue is closed or a write
+/// fails or takes longer than `timeout`
+async fn write_to_client<W: WriteExt + Unpin>(
+    mut writer: W,
+    receiver: Receiver<Arc<Vec<u8>>>,
+    timeout: Duration,
+) {
+    while let Ok(data) = receiver.recv().await {
+        // serial lines are written through a buffer, flush every message
+        let written = io::timeout(timeout, async {
+            writer.write_all(&data).await?;
+            writer.flush().await
+        });
+        if let Err(e) = written.await {
+            log_error!("Failed to send to client: {}", e);
+            break;
+        }
+    }
+}
+
+async fn handle_tcp_client(stream: TcpStream, daemon: SharedDaemon) {
+    let peer = stream
+        .peer_addr()
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+    let serial_header = daemon.lock().unwrap().config().tcpsync_serial_header;
+    handle_client(stream.clone(), stream, serial_header, &peer, daemon).await;
+}
+
+/// Process the control messages the client sends until the connection is
+/// closed
+async fn read_from_client<R: ReadExt + Unpin>(mut reader: R, id: ClientId, daemon: SharedDaemon) {
+    let mut messages = MessageStream::new();
+    let mut buffer = [0u8; 1024];
+    while let Ok(len) = reader.read(&mut buffer).await {
+        if len == 0 {
+            break;
+        }
+        messages.feed(&buffer[..len]);
+        while let Some(message) = messages.next_message() {
+            daemon.lock().unwrap().handle_client_message(id, &message);
+        }
+    }
+}
+
+/// Send the messages to a client and process the control messages it sends,
+/// until the connection is closed or sending to the client fails
+pub(crate) async fn handle_client<R, W>(
+    reader: R,
+    writer: W,
+    serial_header: bool,
+    name: &str,
+    daemon: SharedDaemon,
+) where
+    R: ReadExt + Unpin + Send + 'static,
+    W: WriteExt + Unpin + Send + 'static,
+{
+    let (id, receiver, timeout) = {
+        let mut daemon = daemon.lock().unwrap();
+        let (id, receiver) = daemon.add_client(serial_header);
+        (id, receiver, daemon.config().timeout_on_send)
+    };
+    log_info!("Client connection #{} from {} opened", id, name);
+
+    // closed by whichever of the reader and the writer finishes first
+    let (done_sender, done) = channel::bounded::<()>(1);
+    let writer_done = done_sender.clone();
+    let writer = async_std::task::spawn(async move {
+        write_to_client(writer, receiver, timeout).await;
+        writer_done.close();
+    });
+    daemon.lock().unwrap().set_client_writer(id, writer);
+    let reader_daemon = daemon.clone();
+    let reader = async_std::task::spawn(async move {
+        read_from_client(reader, id, reader_daemon).await;
+        done_sender.close();
+    });
+
+    let _ = done.recv().await;
+    reader.cancel().await;
+    let writer = daemon.lock().unwrap().remove_client(id);
+    if let Some(writer) = writer {
+        writer.cancel().await;
+    }
+    log_info!("Client connection #{} closed", id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_client_does_not_block() {
+        let mut clients = Clients::default();
+        let (_fast, fast_receiver) = clients.add(false);
+        let (slow, slow_receiver) = clients.add(true);
+        let message = DltMessage::new(vec![0x20, 0, 0, 4]);
+
+        for _ in 0..DLT_DAEMON_CLIENT_QUEUE_SIZE + 10 {
+            clients.send(&message);
+            assert_eq!(
+                fast_receiver.try_recv().unwrap().as_slice(),
+                message.as_bytes()
+            );
+        }
+        let slow_client = clients.clients.iter().find(|c| c.id == slow).unwrap();
+        assert_eq!(slow_client.dropped, 10);
+
+        // the count is reset once the client catches up
+        slow_receiver.try_recv().unwrap();
+        clients.send(&message);
+        let slow_client = clients.clients.iter().find(|c| c.id == slow).unwrap();
+        assert_eq!(slow_client.dropped, 0);
+
+        clients.remove(slow);
+        assert_eq!(clients.clients.len(), 1);
+    }
+
+    #[test]
+    fn batch_is_chunked() {
+        let mut clients = Clients::default();
+        let (id, receiver) = clients.add(false);
+        let message = DltMessage::new(vec![0u8; 1000]);
+        clients.send_batch(id, std::iter::repeat_n(message, 100));
+
+        let mut total = 0;
+        while let Ok(chunk) = receiver.try_recv() {
+            assert!(chunk.len() <= DLT_DAEMON_CLIENT_CHUNK_SIZE);
+            total += chunk.len();
+        }
+        assert_eq!(total, 100 * 1000);
+    }
+
+    #[test]
+    fn serial_header() {
+        let mut clients = Clients::default();
+        let (_, receiver) = clients.add(true);
+        clients.send(&DltMessage::new(vec![0x20, 0, 0, 4]));
+        assert_eq!(
+            receiver.try_recv().unwrap().as_slice(),
+            &[b'D', b'L', b'S', 1, 0x20, 0, 0, 4]
+        );
+    }
+
+    #[test]
+    fn failed_send_closes_client() {
+        use crate::test_util::{test_daemon, TestDir};
+        use async_std::os::unix::net::UnixStream;
+        use std::net::Shutdown;
+
+        let dir = TestDir::new("client");
+        let daemon = test_daemon(&dir);
+        async_std::task::block_on(async {
+            // the viewer never sends anything and cannot be written to
+            let (stream, _viewer) = UnixStream::pair().unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let handled = handle_client(stream.clone(), stream, false, "viewer", daemon.clone());
+            io::timeout(Duration::from_secs(5), async {
+                handled.await;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use crate::logging::log_error;
+use libdlt::config::DaemonConfig;
+use std::path::PathBuf;
+
+/// Configuration file used when none is given on the command line
+pub(crate) const DLT_DAEMON_CONF_FILE: &str = "/etc/dlt.conf";
+
+/// Command line switches, identical to the ones of the COVESA dlt-daemon.
+/// Switches given on the command line take precedence over the configuration file.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct CommandLine {
+    pub daemonize: bool,
+    pub help: bool,
+    pub config_file: Option<PathBuf>,
+    pub fifo_directory: Option<PathBuf>,
+    pub control_socket: Option<PathBuf>,
+    pub port: Option<u16>,
+    pub app_id_log_level_file: Option<PathBuf>,
+}
+
+impl CommandLine {
+    /// Parse the arguments following the program name, getopt style: flags
+    /// can be combined (`-dc file`) and values can be attached (`-cfile`).
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut command_line = CommandLine::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(switches) = arg.strip_prefix('-').filter(|s| !s.is_empty()) else {
+                return Err(format!("Unexpected argument {arg}"));
+            };
+            for (i, switch) in switches.char_indices() {
+                match switch {
+                    'd' => command_line.daemonize = true,
+                    'h' => command_line.help = true,
+                    'c' | 't' | 'C' | 'p' | 'a' => {
+                        let attached = &switches[i + 1..];
+                        let value = if attached.is_empty() {
+                            args.next()
+                                .ok_or_else(|| format!("Option -{switch} requires an argument"))?
+                        } else {
+                            attached.to_owned()
+                        };
+                        command_line.set_value(switch, value)?;
+                        break;
+                    }
+                    _ => return Err(format!("Unknown option -{switch}")),
+                }
+            }
+        }
+        Ok(command_line)
+    }
+
+    fn set_value(&mut self, switch: char, value: String) -> Result<(), String> {
+        match switch {
+            'c' => self.config_file = Some(PathBuf::from(value)),
+            't' => self.fifo_directory = Some(PathBuf::from(value)),
+            'C' => self.control_socket = Some(PathBuf::from(value)),
+            'p' => {
+                let port = value
+                    .parse()
+                    .ok()
+                    .filter(|port| *port > 0)
+                    .ok_or_else(|| format!("Invalid port {value}"))?;
+                self.port = Some(port)
+            }
+            'a' => self.app_id_log_level_file = Some(PathBuf::from(value)),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DLT_DAEMON_CONF_FILE))
+    }
+
+    /// Read the configuration file and apply the command line switches.
+    /// Falls back to the default configuration if the file cannot be read.
+    pub fn load_config(&self) -> DaemonConfig {
+        let config_file = self.config_file();
+        let mut config = match DaemonConfig::from_file(&config_file.to_string_lossy()) {
+            Ok(config) => config,
+            Err(e) => {
+                log_error!(
+                    "Cannot load {} ({e}), using default configuration",
+                    config_file.display()
+                );
+                DaemonConfig::default()
+            }
+        };
+        self.apply(&mut config);
+        config
+    }
+
+    /// Override the values read from the configuration file
+    pub fn apply(&self, config: &mut DaemonConfig) {
+        if self.daemonize {
+            config.daemonize = true;
+        }
+        if let Some(fifo_directory) = &self.fifo_directory {
+            config.fifo_directory = fifo_directory.clone();
+        }
+        if let Some(control_socket) = &self.control_socket {
+            config.control_socket_path = control_socket.clone();
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(app_id_log_level_file) = &self.app_id_log_level_file {
+            config.app_id_log_level_file = Some(app_id_log_level_file.clone());
+        }
+    }
+}
+
+pub(crate) fn usage() {
+    println!("Usage: dlt-daemon [options]");
+    println!("Options:");
+    println!("  -d            Daemonize");
+    println!("  -h            Usage");
+    println!("  -c filename   DLT daemon configuration file (Default: {DLT_DAEMON_CONF_FILE})");
+    println!("  -t directory  Directory for local fifo and user-pipes (Default: /tmp)");
+    println!("                (Applications wanting to connect to a daemon using a");
+    println!("                custom directory need to be started with the environment");
+    println!("                variable DLT_PIPE_DIR set appropriately)");
+    println!("  -C filename   DLT daemon control socket (Default: /tmp/dlt-ctrl.sock)");
+    println!("  -p port       port to monitor for incoming requests (Default: 3490)");
+    println!("  -a filename   The filename for load default app id log levels");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CommandLine, String> {
+        CommandLine::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn switches() {
+        let command_line = parse(&[
+            "-d",
+            "-c",
+            "/etc/my.conf",
+            "-t/run/dlt",
+            "-C",
+            "/run/ctrl.sock",
+            "-p",
+            "4490",
+            "-a",
+            "/etc/ll.conf",
+        ])
+        .unwrap();
+        assert_eq!(
+            command_line,
+            CommandLine {
+                daemonize: true,
+                help: false,
+                config_file: Some(PathBuf::from("/etc/my.conf")),
+                fifo_directory: Some(PathBuf::from("/run/dlt")),
+                control_socket: Some(PathBuf::from("/run/ctrl.sock")),
+                port: Some(4490),
+                app_id_log_level_file: Some(PathBuf::from("/etc/ll.conf")),
+            }
+        );
+
+        let command_line = parse(&["-dc", "/etc/my.conf"]).unwrap();
+        assert!(command_line.daemonize);
+        assert_eq!(command_line.config_file(), PathBuf::from("/etc/my.conf"));
+
+        assert!(parse(&["-h"]).unwrap().help);
+        assert_eq!(
+            parse(&[]).unwrap().config_file(),
+            PathBuf::from(DLT_DAEMON_CONF_FILE)
+        );
+    }
+
+    #[test]
+    fn invalid_switches() {
+        assert!(parse(&["-x"]).is_err());
+        assert!(parse(&["-c"]).is_err());
+        assert!(parse(&["-p", "port"]).is_err());
+        assert!(parse(&["file"]).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let mut config = DaemonConfig::default();
+        parse(&["-d", "-t", "/run/dlt", "-C", "/run/ctrl.sock", "-p", "4490"])
+            .unwrap()
+            .apply(&mut config);
+        assert!(config.daemonize);
+        assert_eq!(config.fifo_directory, PathBuf::from("/run/dlt"));
+        assert_eq!(config.control_socket_path, PathBuf::from("/run/ctrl.sock"));
+        assert_eq!(config.port, 4490);
+
+        let mut config = DaemonConfig::default();
+        parse(&[]).unwrap().apply(&mut config);
+        assert_eq!(config.port, 3490);
+        assert_eq!(
+            config.control_socket_path,
+            PathBuf::from("/tmp/dlt-ctrl.sock")
+        );
+    }
+}
@@ -0,0 +1,497 @@
+use crate::message::{
+    parse_header, DltMessage, HeaderInfo, DLT_SERVICE_RESPONSE_OK, DLT_TYPE_CONTROL,
+};
+use crate::registry::Context;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+pub(crate) const DLT_SERVICE_ID_SET_LOG_LEVEL: u32 = 0x01;
+pub(crate) const DLT_SERVICE_ID_SET_TRACE_STATUS: u32 = 0x02;
+pub(crate) const DLT_SERVICE_ID_GET_LOG_INFO: u32 = 0x03;
+pub(crate) const DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL: u32 = 0x04;
+pub(crate) const DLT_SERVICE_ID_SET_DEFAULT_LOG_LEVEL: u32 = 0x11;
+pub(crate) const DLT_SERVICE_ID_SET_DEFAULT_TRACE_STATUS: u32 = 0x12;
+pub(crate) const DLT_SERVICE_ID_GET_SOFTWARE_VERSION: u32 = 0x13;
+pub(crate) const DLT_SERVICE_ID_TIMEZONE: u32 = 0xF03;
+pub(crate) const DLT_SERVICE_ID_OFFLINE_LOGSTORAGE: u32 = 0xF05;
+pub(crate) const DLT_SERVICE_ID_PASSIVE_NODE_CONNECT: u32 = 0xF06;
+pub(crate) const DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS: u32 = 0xF07;
+/// Service ids from here on are injections for the applications
+pub(crate) const DLT_SERVICE_ID_CALLSW_CINJECTION: u32 = 0xFFF;
+
+pub(crate) const DLT_SERVICE_RESPONSE_NOT_SUPPORTED: u8 = 1;
+pub(crate) const DLT_SERVICE_RESPONSE_ERROR: u8 = 2;
+
+/// GetLogInfo status when no context matches the request
+const DLT_GET_LOG_INFO_NO_MATCHING_CONTEXT: u8 = 8;
+/// GetLogInfo status when the response does not fit into one message
+const DLT_GET_LOG_INFO_OVERFLOW: u8 = 9;
+
+/// Largest GetLogInfo payload, leaves room for the headers and the service id
+/// within the 16 bit message length
+const DLT_GET_LOG_INFO_MAX_PAYLOAD: usize = u16::MAX as usize - 64;
+
+/// Size of the mount point field of offline logstorage requests
+const DLT_OFFLINE_LOGSTORAGE_MAX_PATH_LEN: usize = 1024;
+
+// Connection types of offline logstorage requests
+pub(crate) const DLT_OFFLINE_LOGSTORAGE_DEVICE_DISCONNECTED: u8 = 0;
+pub(crate) const DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED: u8 = 1;
+pub(crate) const DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES: u8 = 2;
+
+// Connection status of passive node connect requests
+pub(crate) const DLT_GATEWAY_DISCONNECT: u32 = 0;
+pub(crate) const DLT_GATEWAY_CONNECT: u32 = 1;
+
+/// Passive nodes listed in a connection status response
+const DLT_GATEWAY_MAX_NODES: usize = 255;
+
+/// Version reported by GetSoftwareVersion without an ECU version file
+pub(crate) const DLT_DAEMON_SOFTWARE_VERSION: &str = concat!(
+    "DLT Package Version: ",
+    env!("CARGO_PKG_VERSION"),
+    " STABLE, Package Revision: dlt-daemon-rs"
+);
+
+/// Communication interface appended to GetLogInfo responses
+const DLT_GET_LOG_INFO_COM: &[u8; 4] = b"remo";
+
+const DLT_CONTROL_REQUEST: u8 = 1;
+
+/// A control request sent by a client
+#[derive(Debug, PartialEq)]
+pub(crate) enum ControlRequest {
+    SetLogLevel {
+        app_id: [u8; 4],
+        context_id: [u8; 4],
+        log_level: i8,
+    },
+    SetTraceStatus {
+        app_id: [u8; 4],
+        context_id: [u8; 4],
+        trace_status: i8,
+    },
+    GetLogInfo {
+        options: u8,
+        app_id: [u8; 4],
+        context_id: [u8; 4],
+    },
+    GetDefaultLogLevel,
+    SetDefaultLogLevel(i8),
+    SetDefaultTraceStatus(i8),
+    GetSoftwareVersion,
+    /// Connect or disconnect a logstorage device, or sync its caches. An
+    /// empty mount point syncs all devices.
+    OfflineLogstorage {
+        mount_point: PathBuf,
+        connection_type: u8,
+    },
+    /// Connect or disconnect the passive node of a gateway
+    PassiveNodeConnect {
+        node_id: [u8; 4],
+        connection_status: u32,
+    },
+    PassiveNodeConnectionStatus,
+    /// Data for the injection callback `service_id` of a context
+    Injection {
+        app_id: [u8; 4],
+        context_id: [u8; 4],
+        service_id: u32,
+        data: Vec<u8>,
+    },
+    /// A service the daemon does not implement, or a request too short for its service
+    NotSupported(u32),
+}
+
+fn id(payload: &[u8], offset: usize) -> Option<[u8; 4]> {
+    payload.get(offset..offset + 4)?.try_into().ok()
+}
+
+fn byte(payload: &[u8], offset: usize) -> Option<i8> {
+    payload.get(offset).map(|value| *value as i8)
+}
+
+fn number(payload: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = id(payload, offset)?;
+    if big_endian {
+        Some(u32::from_be_bytes(bytes))
+    } else {
+        Some(u32::from_le_bytes(bytes))
+    }
+}
+
+impl ControlRequest {
+    /// Decode a message received from a client, `None` if it is not a control request
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        let (header, payload) = control_payload(message)?;
+        Self::decode(payload, &header)
+    }
+
+    /// Decode the payload of a control request, which starts with the service id
+    pub fn decode(payload: &[u8], header: &HeaderInfo) -> Option<Self> {
+        let service_id = number(payload, 0, header.big_endian)?;
+        let request = Self::decode_parameters(service_id, payload, header);
+        Some(request.unwrap_or(ControlRequest::NotSupported(service_id)))
+    }
+
+    /// Decode the parameters following the service id, `None` if they are
+    /// incomplete or the service is not supported
+    fn decode_parameters(service_id: u32, payload: &[u8], header: &HeaderInfo) -> Option<Self> {
+        let big_endian = header.big_endian;
+        let request = match service_id {
+            DLT_SERVICE_ID_SET_LOG_LEVEL => ControlRequest::SetLogLevel {
+                app_id: id(payload, 4)?,
+                context_id: id(payload, 8)?,
+                log_level: byte(payload, 12)?,
+            },
+            DLT_SERVICE_ID_SET_TRACE_STATUS => ControlRequest::SetTraceStatus {
+                app_id: id(payload, 4)?,
+                context_id: id(payload, 8)?,
+                trace_status: byte(payload, 12)?,
+            },
+            DLT_SERVICE_ID_GET_LOG_INFO => ControlRequest::GetLogInfo {
+                options: *payload.get(4)?,
+                app_id: id(payload, 5)?,
+                context_id: id(payload, 9)?,
+            },
+            DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL => ControlRequest::GetDefaultLogLevel,
+            DLT_SERVICE_ID_SET_DEFAULT_LOG_LEVEL => {
+                ControlRequest::SetDefaultLogLevel(byte(payload, 4)?)
+            }
+            DLT_SERVICE_ID_SET_DEFAULT_TRACE_STATUS => {
+                ControlRequest::SetDefaultTraceStatus(byte(payload, 4)?)
+            }
+            DLT_SERVICE_ID_GET_SOFTWARE_VERSION => ControlRequest::GetSoftwareVersion,
+            DLT_SERVICE_ID_OFFLINE_LOGSTORAGE => {
+                let end = 4 + DLT_OFFLINE_LOGSTORAGE_MAX_PATH_LEN;
+                let mount_point = payload.get(4..end)?;
+                let len = mount_point
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(mount_point.len());
+                ControlRequest::OfflineLogstorage {
+                    mount_point: PathBuf::from(OsStr::from_bytes(&mount_point[..len])),
+                    connection_type: *payload.get(end)?,
+                }
+            }
+            DLT_SERVICE_ID_PASSIVE_NODE_CONNECT => ControlRequest::PassiveNodeConnect {
+                connection_status: number(payload, 4, big_endian)?,
+                node_id: id(payload, 8)?,
+            },
+            DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS => {
+                ControlRequest::PassiveNodeConnectionStatus
+            }
+            DLT_SERVICE_ID_CALLSW_CINJECTION.. => {
+                let len = number(payload, 4, big_endian)? as usize;
+                ControlRequest::Injection {
+                    app_id: header.app_id,
+                    context_id: header.context_id,
+                    service_id,
+                    data: payload.get(8..8 + len)?.to_vec(),
+                }
+            }
+            _ => return None,
+        };
+        Some(request)
+    }
+
+    pub fn service_id(&self) -> u32 {
+        match self {
+            ControlRequest::SetLogLevel { .. } => DLT_SERVICE_ID_SET_LOG_LEVEL,
+            ControlRequest::SetTraceStatus { .. } => DLT_SERVICE_ID_SET_TRACE_STATUS,
+            ControlRequest::GetLogInfo { .. } => DLT_SERVICE_ID_GET_LOG_INFO,
+            ControlRequest::GetDefaultLogLevel => DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL,
+            ControlRequest::SetDefaultLogLevel(_) => DLT_SERVICE_ID_SET_DEFAULT_LOG_LEVEL,
+            ControlRequest::SetDefaultTraceStatus(_) => DLT_SERVICE_ID_SET_DEFAULT_TRACE_STATUS,
+            ControlRequest::GetSoftwareVersion => DLT_SERVICE_ID_GET_SOFTWARE_VERSION,
+            ControlRequest::OfflineLogstorage { .. } => DLT_SERVICE_ID_OFFLINE_LOGSTORAGE,
+            ControlRequest::PassiveNodeConnect { .. } => DLT_SERVICE_ID_PASSIVE_NODE_CONNECT,
+            ControlRequest::PassiveNodeConnectionStatus => {
+                DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS
+            }
+            ControlRequest::Injection { service_id, .. } => *service_id,
+            ControlRequest::NotSupported(service_id) => *service_id,
+        }
+    }
+}
+
+/// Returns the headers and the payload if `message` is a control request
+fn control_payload(message: &[u8]) -> Option<(HeaderInfo, &[u8])> {
+    let header = parse_header(message)?;
+    if header.message_type != DLT_TYPE_CONTROL || header.message_type_info != DLT_CONTROL_REQUEST {
+        return None;
+    }
+    let payload = message.get(header.payload_offset..)?;
+    Some((header, payload))
+}
+
+/// Response carrying only a status
+pub(crate) fn status_response(ecu_id: &str, service_id: u32, status: u8) -> DltMessage {
+    DltMessage::control_response(ecu_id, service_id, vec![status])
+}
+
+pub(crate) fn default_log_level_response(ecu_id: &str, log_level: i8) -> DltMessage {
+    DltMessage::control_response(
+        ecu_id,
+        DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL,
+        vec![DLT_SERVICE_RESPONSE_OK, log_level as u8],
+    )
+}
+
+pub(crate) fn software_version_response(ecu_id: &str, version: &str) -> DltMessage {
+    let mut payload = vec![DLT_SERVICE_RESPONSE_OK];
+    payload.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    payload.extend_from_slice(version.as_bytes());
+    DltMessage::control_response(ecu_id, DLT_SERVICE_ID_GET_SOFTWARE_VERSION, payload)
+}
+
+/// Offset to UTC in seconds and whether daylight saving time is in effect
+pub(crate) fn timezone_response(ecu_id: &str, timezone: i32, is_dst: bool) -> DltMessage {
+    let mut payload = vec![DLT_SERVICE_RESPONSE_OK];
+    payload.extend_from_slice(&timezone.to_le_bytes());
+    payload.push(is_dst as u8);
+    DltMessage::control_response(ecu_id, DLT_SERVICE_ID_TIMEZONE, payload)
+}
+
+/// Connection status of the passive nodes, given as ECU id and status.
+/// Statuses and ids are sent in fixed size arrays.
+pub(crate) fn passive_node_connection_info_response(
+    ecu_id: &str,
+    nodes: &[([u8; 4], u8)],
+) -> DltMessage {
+    let nodes = &nodes[..nodes.len().min(DLT_GATEWAY_MAX_NODES)];
+    let mut payload = vec![DLT_SERVICE_RESPONSE_OK];
+    payload.extend_from_slice(&(nodes.len() as u32).to_le_bytes());
+    let mut status = [0u8; DLT_GATEWAY_MAX_NODES];
+    let mut node_ids = [0u8; 4 * DLT_GATEWAY_MAX_NODES];
+    for (i, (node_id, node_status)) in nodes.iter().enumerate() {
+        status[i] = *node_status;
+        node_ids[4 * i..4 * i + 4].copy_from_slice(node_id);
+    }
+    payload.extend_from_slice(&status);
+    payload.extend_from_slice(&node_ids);
+    DltMessage::control_response(
+        ecu_id,
+        DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS,
+        payload,
+    )
+}
+
+/// GetLogInfo response listing `contexts`, which must be sorted by application.
+/// Options 4 and 6 add the log level, options 5 and 6 the trace status and
+/// option 7 adds both plus the descriptions of the contexts and applications.
+pub(crate) fn log_info_response<'a>(
+    ecu_id: &str,
+    options: u8,
+    contexts: &[&Context],
+    application_description: impl Fn(&[u8; 4]) -> &'a str,
+) -> DltMessage {
+    if !(3..=7).contains(&options) {
+        return status_response(
+            ecu_id,
+            DLT_SERVICE_ID_GET_LOG_INFO,
+            DLT_SERVICE_RESPONSE_NOT_SUPPORTED,
+        );
+    }
+    if contexts.is_empty() {
+        return status_response(
+            ecu_id,
+            DLT_SERVICE_ID_GET_LOG_INFO,
+            DLT_GET_LOG_INFO_NO_MATCHING_CONTEXT,
+        );
+    }
+    let with_log_level = matches!(options, 4 | 6 | 7);
+    let with_trace_status = matches!(options, 5..=7);
+    let with_description = options == 7;
+
+    let applications: Vec<&[&Context]> = contexts.chunk_by(|a, b| a.app_id == b.app_id).collect();
+    let mut payload = vec![options];
+    payload.extend_from_slice(&(applications.len() as u16).to_le_bytes());
+    for application in applications {
+        let app_id = &application[0].app_id;
+        payload.extend_from_slice(app_id);
+        payload.extend_from_slice(&(application.len() as u16).to_le_bytes());
+        for context in application {
+            payload.extend_from_slice(&context.context_id);
+            if with_log_level {
+                payload.push(context.log_level as u8);
+            }
+            if with_trace_status {
+                payload.push(context.trace_status as u8);
+            }
+            if with_description {
+                push_description(&mut payload, &context.description);
+            }
+        }
+        if with_description {
+            push_description(&mut payload, application_description(app_id));
+        }
+    }
+    payload.extend_from_slice(DLT_GET_LOG_INFO_COM);
+
+    if payload.len() > DLT_GET_LOG_INFO_MAX_PAYLOAD {
+        return status_response(
+            ecu_id,
+            DLT_SERVICE_ID_GET_LOG_INFO,
+            DLT_GET_LOG_INFO_OVERFLOW,
+        );
+    }
+    DltMessage::control_response(ecu_id, DLT_SERVICE_ID_GET_LOG_INFO, payload)
+}
+
+fn push_description(payload: &mut Vec<u8>, description: &str) {
+    payload.extend_from_slice(&(description.len() as u16).to_le_bytes());
+    payload.extend_from_slice(description.as_bytes());
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::message::{MessageStream, DLT_SERIAL_HEADER};
+    use dlt_core::dlt::{
+        ControlType, Endianness, ExtendedHeaderConfig, Message, MessageConfig, MessageType,
+        PayloadContent,
+    };
+
+    /// A control request as sent by a viewer
+    pub(crate) fn control_request(service_id: u32, parameters: &[u8]) -> Vec<u8> {
+        let config = MessageConfig {
+            version: 1,
+            counter: 0,
+            endianness: Endianness::Little,
+            ecu_id: Some("VIEW".to_owned()),
+            session_id: None,
+            timestamp: None,
+            payload: PayloadContent::NonVerbose(service_id, parameters.to_vec()),
+            extended_header_info: Some(ExtendedHeaderConfig {
+                message_type: MessageType::Control(ControlType::Request),
+                app_id: "APP".to_owned(),
+                context_id: "CON".to_owned(),
+            }),
+        };
+        Message::new(config, None).as_bytes()
+    }
+
+    #[test]
+    fn decode_requests() {
+        let mut stream = MessageStream::new();
+        let mut data = DLT_SERIAL_HEADER.to_vec();
+        data.extend(control_request(
+            DLT_SERVICE_ID_SET_LOG_LEVEL,
+            b"APP1CON1\x05remo",
+        ));
+        data.extend(control_request(
+            DLT_SERVICE_ID_GET_LOG_INFO,
+            b"\x06APP1\0\0\0\0remo",
+        ));
+        data.extend(control_request(DLT_SERVICE_ID_GET_SOFTWARE_VERSION, &[]));
+        let mut logstorage = b"/mnt/usb".to_vec();
+        logstorage.resize(DLT_OFFLINE_LOGSTORAGE_MAX_PATH_LEN, 0);
+        logstorage.push(DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES);
+        logstorage.extend_from_slice(b"remo");
+        data.extend(control_request(
+            DLT_SERVICE_ID_OFFLINE_LOGSTORAGE,
+            &logstorage,
+        ));
+        data.extend(control_request(
+            DLT_SERVICE_ID_PASSIVE_NODE_CONNECT,
+            b"\x01\0\0\0ECU2",
+        ));
+        data.extend(control_request(
+            DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS,
+            &[],
+        ));
+        data.extend(control_request(0x1000, b"\x03\0\0\0abc"));
+        data.extend(control_request(0x1234, &[]));
+        // a truncated request is not supported
+        data.extend(control_request(DLT_SERVICE_ID_SET_DEFAULT_LOG_LEVEL, &[]));
+
+        // feed in small pieces
+        let mut requests = Vec::new();
+        for chunk in data.chunks(5) {
+            stream.feed(chunk);
+            while let Some(message) = stream.next_message() {
+                requests.extend(ControlRequest::from_message(&message));
+            }
+        }
+        assert_eq!(
+            requests,
+            vec![
+                ControlRequest::SetLogLevel {
+                    app_id: *b"APP1",
+                    context_id: *b"CON1",
+                    log_level: 5
+                },
+                ControlRequest::GetLogInfo {
+                    options: 6,
+                    app_id: *b"APP1",
+                    context_id: [0; 4]
+                },
+                ControlRequest::GetSoftwareVersion,
+                ControlRequest::OfflineLogstorage {
+                    mount_point: PathBuf::from("/mnt/usb"),
+                    connection_type: DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES
+                },
+                ControlRequest::PassiveNodeConnect {
+                    node_id: *b"ECU2",
+                    connection_status: DLT_GATEWAY_CONNECT
+                },
+                ControlRequest::PassiveNodeConnectionStatus,
+                ControlRequest::Injection {
+                    app_id: *b"APP\0",
+                    context_id: *b"CON\0",
+                    service_id: 0x1000,
+                    data: b"abc".to_vec()
+                },
+                ControlRequest::NotSupported(0x1234),
+                ControlRequest::NotSupported(DLT_SERVICE_ID_SET_DEFAULT_LOG_LEVEL),
+            ]
+        );
+    }
+
+    #[test]
+    fn log_info() {
+        let context = |app_id: &[u8; 4], context_id: &[u8; 4]| Context {
+            app_id: *app_id,
+            context_id: *context_id,
+            pid: 1,
+            connection: None,
+            description: format!("Context {}", context_id[3] as char),
+            log_level: 4,
+            trace_status: 0,
+            log_level_pos: 0,
+            follows_default_log_level: false,
+            follows_default_trace_status: false,
+        };
+        let contexts = [
+            context(b"APP1", b"CON1"),
+            context(b"APP1", b"CON2"),
+            context(b"APP2", b"CON1"),
+        ];
+        let contexts: Vec<&Context> = contexts.iter().collect();
+        let message = log_info_response("ECU1", 4, &contexts, |_| "");
+        // service id, status, applications with their contexts and com
+        assert!(message
+            .as_bytes()
+            .ends_with(b"\x03\0\0\0\x04\x02\0APP1\x02\0CON1\x04CON2\x04APP2\x01\0CON1\x04remo"));
+
+        let message = log_info_response("ECU1", 7, &contexts[2..], |app_id| {
+            assert_eq!(app_id, b"APP2");
+            "App 2"
+        });
+        assert!(message
+            .as_bytes()
+            .ends_with(b"\x07\x01\0APP2\x01\0CON1\x04\0\x09\0Context 1\x05\0App 2remo"));
+
+        assert!(log_info_response("ECU1", 2, &contexts, |_| "")
+            .as_bytes()
+            .ends_with(&[0x03, 0, 0, 0, DLT_SERVICE_RESPONSE_NOT_SUPPORTED]));
+
+        let message = log_info_response("ECU1", 4, &[], |_| "");
+        assert_eq!(
+            message.as_bytes().last(),
+            Some(&DLT_GET_LOG_INFO_NO_MATCHING_CONTEXT)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::control::ControlRequest;
+use crate::daemon::SharedDaemon;
+use crate::logging::log_error;
+use crate::message::MessageStream;
+use async_std::io::{ReadExt, WriteExt};
+use async_std::os::unix::net::{UnixListener, UnixStream};
+use async_std::stream::StreamExt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Tools like `dlt-logstorage-ctrl` running as the daemon group may connect
+const DLT_DAEMON_CONTROL_SOCKET_MODE: u32 = 0o660;
+
+/// Bind a local socket with `mode`, replacing a socket left behind by a
+/// previous instance
+pub(crate) fn bind_unix_socket(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(UnixListener::from(listener))
+}
+
+pub(crate) fn create_control_socket(path: &Path) -> io::Result<UnixListener> {
+    bind_unix_socket(path, DLT_DAEMON_CONTROL_SOCKET_MODE)
+}
+
+/// Accept local control connections. Unlike TCP clients they do not receive
+/// log messages, only the responses to their requests.
+pub(crate) async fn control_socket_server(listener: UnixListener, daemon: SharedDaemon) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                async_std::task::spawn(handle_control_connection(stream, daemon.clone()));
+            }
+            Err(e) => log_error!("Failed to accept control connection: {}", e),
+        }
+    }
+}
+
+async fn handle_control_connection(mut stream: UnixStream, daemon: SharedDaemon) {
+    let mut messages = MessageStream::new();
+    let mut buffer = [0u8; 1024];
+    while let Ok(len) = stream.read(&mut buffer).await {
+        if len == 0 {
+            break;
+        }
+        messages.feed(&buffer[..len]);
+        while let Some(message) = messages.next_message() {
+            // requests on the local socket are meant for this daemon, they
+            // are not forwarded to passive nodes
+            let Some(request) = ControlRequest::from_message(&message) else {
+                continue;
+            };
+            let response = daemon.lock().unwrap().control_response(request);
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                log_error!("Failed to send control response: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::tests::control_request;
+    use crate::control::{
+        DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED, DLT_OFFLINE_LOGSTORAGE_DEVICE_DISCONNECTED,
+        DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES, DLT_SERVICE_ID_OFFLINE_LOGSTORAGE,
+        DLT_SERVICE_RESPONSE_ERROR,
+    };
+    use crate::daemon::Daemon;
+    use crate::logstorage::tests::log_message;
+    use crate::message::DLT_SERVICE_RESPONSE_OK;
+    use crate::test_util::TestDir;
+    use libdlt::config::DaemonConfig;
+    use libdlt::logstorage::DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    fn logstorage_request(mount_point: &Path, connection_type: u8) -> Vec<u8> {
+        let mut params = vec![0u8; 1024];
+        let path = mount_point.to_string_lossy();
+        params[..path.len()].copy_from_slice(path.as_bytes());
+        params.push(connection_type);
+        params.extend_from_slice(b"\0\0\0\0");
+        control_request(DLT_SERVICE_ID_OFFLINE_LOGSTORAGE, &params)
+    }
+
+    /// Send a request and return the status of the response
+    async fn request(stream: &mut UnixStream, request: &[u8]) -> u8 {
+        stream.write_all(request).await.unwrap();
+        let mut message = vec![0u8; 4];
+        stream.read_exact(&mut message).await.unwrap();
+        let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+        message.resize(len, 0);
+        stream.read_exact(&mut message[4..]).await.unwrap();
+        *message.last().unwrap()
+    }
+
+    #[test]
+    fn connect_and_disconnect_device() {
+        let dir = TestDir::new("ctrl");
+        let device = dir.join("usb");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(
+            device.join(DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME),
+            "[FILTER1]\nLogAppName=APP1\nLogLevel=DLT_LOG_INFO\nFile=app1\nFileSize=1000\n\
+             NOFiles=1\nSyncBehavior=ON_DEVICE_DISCONNECT\n",
+        )
+        .unwrap();
+        let config = DaemonConfig {
+            offline_logstorage_max_devices: 1,
+            offline_logstorage_timestamp: false,
+            persistance_storage_path: dir.to_path_buf(),
+            ..Default::default()
+        };
+        let daemon = Arc::new(Mutex::new(Daemon::new(config)));
+        let socket: PathBuf = dir.join("dlt-ctrl.sock");
+        let size = || {
+            fs::metadata(device.join("app1_1.dlt"))
+                .map(|m| m.len())
+                .unwrap_or(0)
+        };
+
+        async_std::task::block_on(async {
+            let listener = create_control_socket(&socket).unwrap();
+            async_std::task::spawn(control_socket_server(listener, daemon.clone()));
+            let mut stream = UnixStream::connect(&socket).await.unwrap();
+
+            let connect = logstorage_request(&device, DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED);
+            assert_eq!(
+                request(&mut stream, &connect).await,
+                DLT_SERVICE_RESPONSE_OK
+            );
+            // already connected
+            assert_eq!(
+                request(&mut stream, &connect).await,
+                DLT_SERVICE_RESPONSE_ERROR
+            );
+
+            daemon
+                .lock()
+                .unwrap()
+                .forward_message(&log_message(b"APP1", b"CON1", 4, 10));
+            let sync = logstorage_request(&device, DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES);
+            assert_eq!(request(&mut stream, &sync).await, DLT_SERVICE_RESPONSE_OK);
+            // cached until the device is disconnected
+            assert_eq!(size(), 0);
+
+            let disconnect =
+                logstorage_request(&device, DLT_OFFLINE_LOGSTORAGE_DEVICE_DISCONNECTED);
+            assert_eq!(
+                request(&mut stream, &disconnect).await,
+                DLT_SERVICE_RESPONSE_OK
+            );
+            assert_eq!(size(), 16 + 28);
+            assert_eq!(
+                request(&mut stream, &disconnect).await,
+                DLT_SERVICE_RESPONSE_ERROR
+            );
+        });
+    }
+}
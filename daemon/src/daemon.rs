@@ -0,0 +1,1690 @@
+use crate::client::{tcp_server, ClientId, Clients};
+use crate::cmdline::CommandLine;
+use crate::control::{
+    default_log_level_response, log_info_response, passive_node_connection_info_response,
+    software_version_response, status_response, timezone_response, ControlRequest,
+    DLT_DAEMON_SOFTWARE_VERSION, DLT_GATEWAY_CONNECT, DLT_GATEWAY_DISCONNECT,
+    DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED, DLT_OFFLINE_LOGSTORAGE_DEVICE_DISCONNECTED,
+    DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES, DLT_SERVICE_RESPONSE_ERROR,
+    DLT_SERVICE_RESPONSE_NOT_SUPPORTED,
+};
+use crate::control_socket::{control_socket_server, create_control_socket};
+use crate::daemonize::{drop_privileges, remove_pid_file, write_pid_file};
+#[cfg(not(feature = "unix_socket"))]
+use crate::fifo::create_daemon_fifo;
+#[cfg(feature = "shm")]
+use crate::fifo::set_fifo_group;
+use crate::fifo::DLT_DAEMON_FIFO;
+use crate::gateway::{run_gateway, Gateway};
+use crate::logging::{log_debug, log_error, log_info, log_notice, log_warning};
+use crate::logstorage::LogstorageDevice;
+use crate::message::{
+    local_timezone, parse_header, DltMessage, DLT_CONNECTION_STATUS_CONNECTED,
+    DLT_CONNECTION_STATUS_DISCONNECTED, DLT_SERVICE_RESPONSE_OK,
+};
+use crate::offline_trace::OfflineTrace;
+use crate::registry::{
+    load_log_level_preconfig, Application, Context, PreconfiguredLogLevel, Registry,
+};
+use crate::ring_buffer::RingBuffer;
+use crate::serial::{open_serial_port, serial_client};
+use crate::signals::Signal;
+use crate::udp::UdpMulticast;
+#[cfg(feature = "unix_socket")]
+use crate::user_socket::{create_user_socket, user_socket_server};
+use crate::user_socket::{
+    create_vsock_listener, ipc_tcp_server, vsock_server, ConnectionId, UserChannels,
+};
+use async_std::channel::Receiver;
+use async_std::io::{Read, ReadExt};
+use async_std::net::{SocketAddr, TcpListener};
+use async_std::task::JoinHandle;
+#[cfg(feature = "shm")]
+use dlt_user::shm::{SharedMemory, DLT_SHM_NAME};
+use dlt_user::user_header::user_control_message::{self, UserControlMessage};
+use dlt_user::user_header::{UserMessage, UserMessageStream};
+use dlt_user::LogState;
+use libdlt::{config::DaemonConfig, error::DltError};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Size of the buffer used to read from the application channels
+const DLT_DAEMON_RCVBUFSIZE: usize = 10024;
+
+/// Communication interface reported in connection info messages
+const DLT_DAEMON_COMMUNICATION_INTERFACE: &[u8; 4] = b"remo";
+
+/// Interval for checking registered processes and persisting the registry
+const DLT_DAEMON_HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time given to the clients to receive their queued messages on shutdown
+const DLT_DAEMON_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub(crate) type SharedDaemon = Arc<Mutex<Daemon>>;
+
+/// Format a 4 byte DLT id, dropping the trailing zero padding
+pub(crate) fn id_to_string(id: &[u8; 4]) -> String {
+    String::from_utf8_lossy(id)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+/// Highest valid log level (verbose) and trace status (on)
+const DLT_LOG_VERBOSE: i8 = 6;
+const DLT_TRACE_STATUS_ON: i8 = 1;
+
+/// Tell the application owning `context` about its log level and trace status
+fn send_log_level(user_channels: &UserChannels, context: &Context) {
+    if context.pid == 0 {
+        // loaded from the runtime configuration, the application is not running
+        return;
+    }
+    let log_level = user_control_message::LogLevel {
+        log_level: context.log_level as u8,
+        trace_status: context.trace_status as u8,
+        log_level_pos: context.log_level_pos,
+    };
+    let message = log_level.to_user_message(&[]);
+    if let Err(e) = user_channels.send(context.pid, context.connection, &message) {
+        log_error!(
+            "Failed to send log level to ApplicationID '{}': {}",
+            id_to_string(&context.app_id),
+            e
+        );
+    }
+}
+
+/// Tell `application` whether a client receives its logs
+fn send_log_state(user_channels: &UserChannels, application: &Application, log_state: LogState) {
+    if application.pid == 0 {
+        return;
+    }
+    let message = user_control_message::LogState {
+        log_state: log_state as i8,
+    };
+    let message = message.to_user_message(&[]);
+    if let Err(e) = user_channels.send(application.pid, application.connection, &message) {
+        log_error!(
+            "Failed to send log state to ApplicationID '{}': {}",
+            id_to_string(&application.app_id),
+            e
+        );
+    }
+}
+
+/// Whether an entry registered on `owner` by the application with `pid` is
+/// owned by another channel than `connection`. Applications on a connection
+/// cannot take over the entries of running local applications or of other
+/// connections.
+fn owned_by_other_channel(
+    owner: Option<ConnectionId>,
+    pid: u32,
+    connection: Option<ConnectionId>,
+) -> bool {
+    match (owner, connection) {
+        (Some(owner), _) => Some(owner) != connection,
+        (None, Some(_)) => pid != 0,
+        (None, None) => false,
+    }
+}
+
+/// Send the new levels to the applications of the changed contexts and
+/// build the response, which is an error if no context matched
+fn send_log_levels(
+    config: &DaemonConfig,
+    user_channels: &UserChannels,
+    service_id: u32,
+    changed: Vec<&Context>,
+) -> DltMessage {
+    let status = if changed.is_empty() {
+        DLT_SERVICE_RESPONSE_ERROR
+    } else {
+        DLT_SERVICE_RESPONSE_OK
+    };
+    for context in changed {
+        send_log_level(user_channels, context);
+    }
+    status_response(&config.ecu_id, service_id, status)
+}
+
+fn open_offline_trace(config: &DaemonConfig) -> Option<OfflineTrace> {
+    let directory = config.offline_trace_directory.as_ref()?;
+    match OfflineTrace::new(directory, config) {
+        Ok(offline_trace) => Some(offline_trace),
+        Err(e) => {
+            log_error!(
+                "Cannot open offline trace in {}: {}",
+                directory.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Highest log level and trace status of all contexts, if the configuration enforces them
+fn context_limit(config: &DaemonConfig) -> Option<(i8, i8)> {
+    config.force_context_loglevel_and_tracestatus.then_some((
+        config.context_log_level as i8,
+        config.context_trace_status as i8,
+    ))
+}
+
+/// Version reported by GetSoftwareVersion, read from the ECU version file if configured
+fn ecu_software_version(config: &DaemonConfig) -> String {
+    let Some(path) = &config.path_to_ecusoftware_version else {
+        return DLT_DAEMON_SOFTWARE_VERSION.to_owned();
+    };
+    match std::fs::read_to_string(path) {
+        Ok(version) => version.trim_end().to_owned(),
+        Err(e) => {
+            log_error!(
+                "Cannot read ECU software version from {}: {}",
+                path.display(),
+                e
+            );
+            DLT_DAEMON_SOFTWARE_VERSION.to_owned()
+        }
+    }
+}
+
+/// Context log levels preconfigured in the file given with `-a`
+fn log_level_preconfig(config: &DaemonConfig) -> Vec<PreconfiguredLogLevel> {
+    let Some(path) = &config.app_id_log_level_file else {
+        return Vec::new();
+    };
+    load_log_level_preconfig(path).unwrap_or_else(|e| {
+        log_error!(
+            "Cannot load log level preconfiguration from {}: {}",
+            path.display(),
+            e
+        );
+        Vec::new()
+    })
+}
+
+/// Directory the shared memory segments are visible in
+#[cfg(feature = "shm")]
+const DLT_SHM_DIRECTORY: &str = "/dev/shm";
+
+/// Create the shared memory the applications log to, they fall back to the
+/// FIFO if it is missing
+#[cfg(feature = "shm")]
+fn create_shared_memory(config: &DaemonConfig) -> Option<SharedMemory> {
+    match SharedMemory::create(DLT_SHM_NAME, config.shared_memory_size as usize) {
+        Ok(shm) => Some(shm),
+        Err(e) => {
+            log_error!("Cannot create shared memory {}: {}", DLT_SHM_NAME, e);
+            None
+        }
+    }
+}
+
+fn open_udp_multicast(config: &DaemonConfig) -> Option<UdpMulticast> {
+    if !config.udpconnection_setup {
+        return None;
+    }
+    let Ok(port) = u16::try_from(config.udpmulticast_ipport) else {
+        log_warning!("Invalid UDP multicast port {}", config.udpmulticast_ipport);
+        return None;
+    };
+    match UdpMulticast::new(config.udpmulticast_ipaddress, port) {
+        Ok(udp_multicast) => Some(udp_multicast),
+        Err(e) => {
+            log_error!("Cannot open UDP multicast socket: {}", e);
+            None
+        }
+    }
+}
+
+/// Connect the logstorage directory of the configuration file, if any
+fn connect_internal_storage(config: &DaemonConfig) -> Option<LogstorageDevice> {
+    if config.offline_logstorage_max_devices == 0 {
+        return None;
+    }
+    let mount_point = config.offline_logstorage_dir_path.as_ref()?;
+    match LogstorageDevice::connect(mount_point, config) {
+        Ok(device) => Some(device),
+        Err(e) => {
+            log_error!(
+                "Cannot connect logstorage device {}: {}",
+                mount_point.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+pub(crate) struct Daemon {
+    config: DaemonConfig,
+    registry: Registry,
+    clients: Clients,
+    /// Messages received while no client is connected
+    ring_buffer: RingBuffer,
+    offline_trace: Option<OfflineTrace>,
+    udp_multicast: Option<UdpMulticast>,
+    /// Reported by GetSoftwareVersion
+    ecu_version: String,
+    /// Connected offline logstorage devices
+    logstorage: Vec<LogstorageDevice>,
+    /// Mount point of the logstorage directory of the configuration file, it
+    /// does not count against `OfflineLogstorageMaxDevices`
+    internal_storage: Option<PathBuf>,
+    gateway: Gateway,
+    /// Log levels and injections are sent to the applications on these
+    user_channels: UserChannels,
+    /// Message counter of the messages sent by `log_internal`
+    internal_counter: u8,
+    /// Log messages of the applications, announced with `LogShm`
+    #[cfg(feature = "shm")]
+    shm: Option<SharedMemory>,
+}
+
+impl Daemon {
+    pub fn new(config: DaemonConfig) -> Self {
+        let mut registry = Registry::new(
+            config.context_log_level as i8,
+            config.context_trace_status as i8,
+        );
+        if let Err(e) = registry.load(&config.persistance_storage_path) {
+            log_error!("Failed to load runtime configuration: {}", e);
+        }
+        registry.set_preconfig(log_level_preconfig(&config));
+        registry.set_limit(context_limit(&config));
+        let ring_buffer = RingBuffer::new(
+            config.ring_buffer_min_size,
+            config.ring_buffer_max_size,
+            config.ring_buffer_step_size,
+        );
+        let offline_trace = open_offline_trace(&config);
+        let udp_multicast = open_udp_multicast(&config);
+        let ecu_version = ecu_software_version(&config);
+        let logstorage: Vec<LogstorageDevice> =
+            connect_internal_storage(&config).into_iter().collect();
+        let internal_storage = logstorage
+            .first()
+            .map(|device| device.mount_point().to_owned());
+        let gateway = Gateway::new(&config);
+        #[cfg(not(feature = "unix_socket"))]
+        let user_channels = UserChannels::new(Some(&config.fifo_directory));
+        #[cfg(feature = "unix_socket")]
+        let user_channels = UserChannels::new(None);
+        Daemon {
+            config,
+            registry,
+            clients: Clients::default(),
+            ring_buffer,
+            offline_trace,
+            udp_multicast,
+            ecu_version,
+            logstorage,
+            internal_storage,
+            gateway,
+            user_channels,
+            internal_counter: 0,
+            #[cfg(feature = "shm")]
+            shm: None,
+        }
+    }
+
+    pub fn user_channels_mut(&mut self) -> &mut UserChannels {
+        &mut self.user_channels
+    }
+
+    /// Take over the shared memory created at startup
+    #[cfg(feature = "shm")]
+    pub fn set_shared_memory(&mut self, shm: SharedMemory) {
+        self.shm = Some(shm);
+    }
+
+    /// Forward the messages the applications stored in the shared memory
+    #[cfg(feature = "shm")]
+    fn drain_shared_memory(&mut self) {
+        while let Some(bytes) = self.shm.as_ref().and_then(SharedMemory::pop) {
+            // the length field of the standard header covers the whole message
+            if bytes.len() < 4 || u16::from_be_bytes([bytes[2], bytes[3]]) as usize != bytes.len() {
+                log_warning!("Invalid message in shared memory dropped");
+                continue;
+            }
+            self.forward_message(&DltMessage::new(bytes));
+        }
+    }
+
+    pub fn config(&self) -> &DaemonConfig {
+        &self.config
+    }
+
+    pub fn gateway(&self) -> &Gateway {
+        &self.gateway
+    }
+
+    pub fn gateway_mut(&mut self) -> &mut Gateway {
+        &mut self.gateway
+    }
+
+    /// Register a new client connection
+    pub fn add_client(&mut self, serial_header: bool) -> (ClientId, Receiver<Arc<Vec<u8>>>) {
+        let (id, receiver) = self.clients.add(serial_header);
+        if !self.ring_buffer.is_empty() {
+            self.clients.send_batch(id, self.ring_buffer.drain());
+        }
+        self.send_connection_info(DLT_CONNECTION_STATUS_CONNECTED);
+        if self.config.send_ecusoftware_version > 0 {
+            self.send_software_version();
+        }
+        if self.config.send_timezone > 0 {
+            self.send_timezone();
+        }
+
+        let overflow_counter = self.ring_buffer.take_overflow_counter();
+        if overflow_counter > 0 {
+            log_warning!(
+                "Buffer full, {} messages lost while no client was connected",
+                overflow_counter
+            );
+            let message = DltMessage::buffer_overflow(&self.config.ecu_id, overflow_counter);
+            self.forward_message(&message);
+        }
+        self.log_internal(&format!(
+            "New client connection #{} established, Total Clients : {}",
+            id,
+            self.clients.len()
+        ));
+        if self.clients.len() == 1 {
+            self.send_log_states();
+        }
+        (id, receiver)
+    }
+
+    /// Tell the connected clients which software produced the logs
+    pub fn send_software_version(&mut self) {
+        if !self.clients.is_empty() {
+            let message = software_version_response(&self.config.ecu_id, &self.ecu_version);
+            self.clients.send(&message);
+        }
+    }
+
+    /// Tell the connected clients the timezone of the ECU
+    pub fn send_timezone(&mut self) {
+        if !self.clients.is_empty() {
+            let (timezone, is_dst) = local_timezone();
+            let message = timezone_response(&self.config.ecu_id, timezone, is_dst);
+            self.clients.send(&message);
+        }
+    }
+
+    pub fn set_client_writer(&mut self, id: ClientId, writer: JoinHandle<()>) {
+        self.clients.set_writer(id, writer);
+    }
+
+    /// Remove a client connection, returns the task writing to it
+    pub fn remove_client(&mut self, id: ClientId) -> Option<JoinHandle<()>> {
+        let had_clients = !self.clients.is_empty();
+        let writer = self.clients.remove(id);
+        self.send_connection_info(DLT_CONNECTION_STATUS_DISCONNECTED);
+        self.log_internal(&format!(
+            "Client connection #{} closed. Total Clients : {}",
+            id,
+            self.clients.len()
+        ));
+        if had_clients && self.clients.is_empty() {
+            self.send_log_states();
+        }
+        writer
+    }
+
+    /// Connected while at least one client receives the logs
+    fn log_state(&self) -> LogState {
+        if self.clients.is_empty() {
+            LogState::Disconnected
+        } else {
+            LogState::Connected
+        }
+    }
+
+    /// Tell all applications whether a client receives their logs
+    fn send_log_states(&self) {
+        let log_state = self.log_state();
+        for application in self.registry.applications() {
+            send_log_state(&self.user_channels, application, log_state);
+        }
+    }
+
+    /// Report the status of the daemon in the DLT stream, under the
+    /// application `DLTD` and context `INTM`
+    pub fn log_internal(&mut self, text: &str) {
+        let message = DltMessage::internal_log(&self.config.ecu_id, self.internal_counter, text);
+        self.internal_counter = self.internal_counter.wrapping_add(1);
+        self.forward_message(&message);
+    }
+
+    fn send_connection_info(&mut self, state: u8) {
+        let message = DltMessage::connection_info(
+            &self.config.ecu_id,
+            state,
+            DLT_DAEMON_COMMUNICATION_INTERFACE,
+        );
+        self.forward_message(&message);
+    }
+
+    /// Send a message to all outputs, or keep it until a client connects
+    pub fn forward_message(&mut self, message: &DltMessage) {
+        if let Some(offline_trace) = &mut self.offline_trace {
+            if let Err(e) = offline_trace.write(message) {
+                log_error!("Offline trace stopped: {}", e);
+                self.offline_trace = None;
+            }
+        }
+        if !self.logstorage.is_empty() {
+            if let Some(header) = message.header() {
+                for device in self.logstorage.iter_mut() {
+                    device.write(message, &header);
+                }
+            }
+        }
+        if let Some(udp_multicast) = &mut self.udp_multicast {
+            udp_multicast.send(message);
+        }
+        if self.clients.is_empty() {
+            self.ring_buffer.push(message.clone());
+        } else {
+            self.clients.send(message);
+        }
+    }
+
+    /// Drop applications whose process has exited and persist registry changes
+    pub fn housekeeping(&mut self) {
+        // in case a notification got lost in a full FIFO
+        #[cfg(feature = "shm")]
+        self.drain_shared_memory();
+        for app_id in self.registry.remove_exited_applications() {
+            log_info!(
+                "ApplicationID '{}' removed, process exited",
+                id_to_string(&app_id)
+            );
+        }
+        if self.registry.take_dirty() {
+            if let Err(e) = self.registry.save(&self.config.persistance_storage_path) {
+                log_error!("Failed to store runtime configuration: {}", e);
+            }
+        }
+    }
+
+    /// Take over a reloaded configuration. The FIFO, the sockets and the
+    /// process settings stay as they were set up at startup.
+    pub fn reload(&mut self, mut config: DaemonConfig) {
+        config.daemonize = self.config.daemonize;
+        config.logging_mode = self.config.logging_mode.clone();
+        config.logging_level = self.config.logging_level;
+        config.logging_filename = self.config.logging_filename.clone();
+        config.fifo_directory = self.config.fifo_directory.clone();
+        config.daemon_fifo_size = self.config.daemon_fifo_size;
+        config.daemon_fifo_group = self.config.daemon_fifo_group.clone();
+        config.port = self.config.port;
+        config.bind_address = self.config.bind_address;
+        config.control_socket_path = self.config.control_socket_path.clone();
+        config.pid_file = self.config.pid_file.clone();
+        config.daemon_user = self.config.daemon_user.clone();
+        config.daemon_group = self.config.daemon_group.clone();
+        config.serial_port = self.config.serial_port.clone();
+        config.rs232_baudrate = self.config.rs232_baudrate;
+        config.send_ecusoftware_version = self.config.send_ecusoftware_version;
+        config.send_timezone = self.config.send_timezone;
+        config.gateway_mode = self.config.gateway_mode;
+        config.gateway_config_file = self.config.gateway_config_file.clone();
+
+        for context in self.registry.set_defaults(
+            config.context_log_level as i8,
+            config.context_trace_status as i8,
+        ) {
+            send_log_level(&self.user_channels, context);
+        }
+        self.registry.set_preconfig(log_level_preconfig(&config));
+        for context in self.registry.set_limit(context_limit(&config)) {
+            send_log_level(&self.user_channels, context);
+        }
+        self.ring_buffer.set_limits(
+            config.ring_buffer_min_size,
+            config.ring_buffer_max_size,
+            config.ring_buffer_step_size,
+        );
+        // settings of the offline trace may have changed, continue in a new file
+        self.offline_trace = open_offline_trace(&config);
+        self.udp_multicast = open_udp_multicast(&config);
+        self.ecu_version = ecu_software_version(&config);
+        self.config = config;
+    }
+
+    /// Persist the registry, write the logstorage caches and close all client queues. Returns the writer
+    /// tasks, which finish once the queued messages are sent.
+    pub fn shutdown(&mut self) -> Vec<JoinHandle<()>> {
+        for device in self.logstorage.iter_mut() {
+            device.flush();
+        }
+        if let Err(e) = self.registry.save(&self.config.persistance_storage_path) {
+            log_error!("Failed to store runtime configuration: {}", e);
+        }
+        self.clients.close_all()
+    }
+
+    /// GetLogInfo response for the contexts matching `app_id` and `context_id`
+    fn log_info(&self, options: u8, app_id: &[u8; 4], context_id: &[u8; 4]) -> DltMessage {
+        let contexts = self.registry.matching_contexts(app_id, context_id);
+        log_info_response(&self.config.ecu_id, options, &contexts, |app_id| {
+            self.registry.application_description(app_id)
+        })
+    }
+
+    /// Process a message of a client and send the response to it
+    pub fn handle_client_message(&mut self, client: ClientId, message: &[u8]) {
+        if let Some(response) = self.client_message(message) {
+            self.clients.send_to(client, &response);
+        }
+    }
+
+    /// Forward a control request addressed to a passive node or process it.
+    /// Returns the response, `None` if the message is not answered here.
+    pub fn client_message(&mut self, message: &[u8]) -> Option<DltMessage> {
+        let request = ControlRequest::from_message(message)?;
+        let ecu_id = parse_header(message).and_then(|header| header.ecu_id);
+        if let Some(ecu_id) = ecu_id.filter(|ecu_id| self.gateway.is_passive_node(ecu_id)) {
+            if self.gateway.send(&ecu_id, message) {
+                return None;
+            }
+            log_warning!("Passive node {} not connected", id_to_string(&ecu_id));
+            return Some(status_response(
+                &self.config.ecu_id,
+                request.service_id(),
+                DLT_SERVICE_RESPONSE_ERROR,
+            ));
+        }
+        Some(self.control_response(request))
+    }
+
+    /// Process a control request and build the response
+    pub fn control_response(&mut self, request: ControlRequest) -> DltMessage {
+        let service_id = request.service_id();
+        let ecu_id = &self.config.ecu_id;
+        match request {
+            ControlRequest::SetLogLevel {
+                app_id,
+                context_id,
+                log_level,
+            } => {
+                let log_level = if log_level < 0 {
+                    self.registry.default_log_level()
+                } else {
+                    log_level
+                };
+                if log_level > self.max_log_level() {
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_ERROR)
+                } else {
+                    let changed = self.registry.set_log_level(&app_id, &context_id, log_level);
+                    send_log_levels(&self.config, &self.user_channels, service_id, changed)
+                }
+            }
+            ControlRequest::SetTraceStatus {
+                app_id,
+                context_id,
+                trace_status,
+            } => {
+                let trace_status = if trace_status < 0 {
+                    self.registry.default_trace_status()
+                } else {
+                    trace_status
+                };
+                if trace_status > self.max_trace_status() {
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_ERROR)
+                } else {
+                    let changed =
+                        self.registry
+                            .set_trace_status(&app_id, &context_id, trace_status);
+                    send_log_levels(&self.config, &self.user_channels, service_id, changed)
+                }
+            }
+            ControlRequest::GetLogInfo {
+                options,
+                app_id,
+                context_id,
+            } => self.log_info(options, &app_id, &context_id),
+            ControlRequest::GetDefaultLogLevel => {
+                default_log_level_response(ecu_id, self.registry.default_log_level())
+            }
+            ControlRequest::SetDefaultLogLevel(log_level) => {
+                if (0..=self.max_log_level()).contains(&log_level) {
+                    let trace_status = self.registry.default_trace_status();
+                    for context in self.registry.set_defaults(log_level, trace_status) {
+                        send_log_level(&self.user_channels, context);
+                    }
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_OK)
+                } else {
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_ERROR)
+                }
+            }
+            ControlRequest::SetDefaultTraceStatus(trace_status) => {
+                if (0..=self.max_trace_status()).contains(&trace_status) {
+                    let log_level = self.registry.default_log_level();
+                    for context in self.registry.set_defaults(log_level, trace_status) {
+                        send_log_level(&self.user_channels, context);
+                    }
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_OK)
+                } else {
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_ERROR)
+                }
+            }
+            ControlRequest::GetSoftwareVersion => {
+                software_version_response(ecu_id, &self.ecu_version)
+            }
+            ControlRequest::OfflineLogstorage {
+                mount_point,
+                connection_type,
+            } => {
+                let status = self.offline_logstorage(&mount_point, connection_type);
+                status_response(&self.config.ecu_id, service_id, status)
+            }
+            ControlRequest::PassiveNodeConnect {
+                node_id,
+                connection_status,
+            } => {
+                let done = match connection_status {
+                    DLT_GATEWAY_CONNECT => self.gateway.connect(&node_id),
+                    DLT_GATEWAY_DISCONNECT => self.gateway.disconnect(&node_id),
+                    _ => false,
+                };
+                let status = if done {
+                    DLT_SERVICE_RESPONSE_OK
+                } else {
+                    log_warning!(
+                        "Passive node {} cannot be changed to connection status {}",
+                        id_to_string(&node_id),
+                        connection_status
+                    );
+                    DLT_SERVICE_RESPONSE_ERROR
+                };
+                status_response(ecu_id, service_id, status)
+            }
+            ControlRequest::PassiveNodeConnectionStatus => {
+                if self.gateway.is_empty() {
+                    status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_ERROR)
+                } else {
+                    passive_node_connection_info_response(ecu_id, &self.gateway.connection_status())
+                }
+            }
+            ControlRequest::Injection {
+                app_id,
+                context_id,
+                service_id,
+                data,
+            } => {
+                let status = self.inject(&app_id, &context_id, service_id, &data);
+                status_response(ecu_id, service_id, status)
+            }
+            ControlRequest::NotSupported(_) => {
+                status_response(ecu_id, service_id, DLT_SERVICE_RESPONSE_NOT_SUPPORTED)
+            }
+        }
+    }
+
+    /// Highest log level a client may set
+    fn max_log_level(&self) -> i8 {
+        context_limit(&self.config).map_or(DLT_LOG_VERBOSE, |(log_level, _)| log_level)
+    }
+
+    fn max_trace_status(&self) -> i8 {
+        context_limit(&self.config).map_or(DLT_TRACE_STATUS_ON, |(_, trace_status)| trace_status)
+    }
+
+    /// Forward injection data to the application owning the context, returns
+    /// the response status
+    fn inject(&self, app_id: &[u8; 4], context_id: &[u8; 4], service_id: u32, data: &[u8]) -> u8 {
+        if !self.config.injection_mode {
+            log_warning!("Injections are disabled");
+            return DLT_SERVICE_RESPONSE_ERROR;
+        }
+        let Some(context) = self
+            .registry
+            .context(app_id, context_id)
+            .filter(|context| context.pid != 0)
+        else {
+            log_warning!(
+                "Injection for unknown context {}:{}",
+                id_to_string(app_id),
+                id_to_string(context_id)
+            );
+            return DLT_SERVICE_RESPONSE_ERROR;
+        };
+        let injection = user_control_message::Injection {
+            log_level_pos: context.log_level_pos,
+            service_id,
+            data_length_inject: data.len() as u32,
+        };
+        match self.user_channels.send(
+            context.pid,
+            context.connection,
+            &injection.to_user_message(data),
+        ) {
+            Ok(()) => DLT_SERVICE_RESPONSE_OK,
+            Err(e) => {
+                log_error!(
+                    "Failed to send injection to ApplicationID '{}': {}",
+                    id_to_string(app_id),
+                    e
+                );
+                DLT_SERVICE_RESPONSE_ERROR
+            }
+        }
+    }
+
+    /// Number of logstorage devices connected by control requests
+    fn connected_devices(&self) -> usize {
+        self.logstorage
+            .iter()
+            .filter(|device| Some(device.mount_point()) != self.internal_storage.as_deref())
+            .count()
+    }
+
+    /// Connect, disconnect or sync a logstorage device, returns the response status
+    fn offline_logstorage(&mut self, mount_point: &Path, connection_type: u8) -> u8 {
+        if self.config.offline_logstorage_max_devices == 0 {
+            log_warning!("Logstorage functionality not enabled");
+            return DLT_SERVICE_RESPONSE_ERROR;
+        }
+        let connected = self
+            .logstorage
+            .iter()
+            .position(|device| device.mount_point() == mount_point);
+        match connection_type {
+            DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED => {
+                if connected.is_some() {
+                    log_warning!(
+                        "Logstorage device {} already connected",
+                        mount_point.display()
+                    );
+                    return DLT_SERVICE_RESPONSE_ERROR;
+                }
+                if self.connected_devices() >= self.config.offline_logstorage_max_devices as usize {
+                    log_warning!(
+                        "No free logstorage device slot for {}",
+                        mount_point.display()
+                    );
+                    return DLT_SERVICE_RESPONSE_ERROR;
+                }
+                match LogstorageDevice::connect(mount_point, &self.config) {
+                    Ok(device) => {
+                        log_info!("Logstorage device {} connected", mount_point.display());
+                        self.logstorage.push(device);
+                        DLT_SERVICE_RESPONSE_OK
+                    }
+                    Err(e) => {
+                        log_error!(
+                            "Cannot connect logstorage device {}: {}",
+                            mount_point.display(),
+                            e
+                        );
+                        DLT_SERVICE_RESPONSE_ERROR
+                    }
+                }
+            }
+            DLT_OFFLINE_LOGSTORAGE_DEVICE_DISCONNECTED => match connected {
+                Some(index) => {
+                    let mut device = self.logstorage.remove(index);
+                    device.flush();
+                    log_info!("Logstorage device {} disconnected", mount_point.display());
+                    DLT_SERVICE_RESPONSE_OK
+                }
+                None => {
+                    log_warning!("Logstorage device {} not connected", mount_point.display());
+                    DLT_SERVICE_RESPONSE_ERROR
+                }
+            },
+            DLT_OFFLINE_LOGSTORAGE_SYNC_CACHES => {
+                for device in self.logstorage.iter_mut() {
+                    if mount_point.as_os_str().is_empty() || device.mount_point() == mount_point {
+                        device.sync_on_demand();
+                    }
+                }
+                DLT_SERVICE_RESPONSE_OK
+            }
+            _ => DLT_SERVICE_RESPONSE_NOT_SUPPORTED,
+        }
+    }
+
+    /// Forget an application connection and unregister the applications
+    /// that registered on it
+    pub fn remove_connection(&mut self, connection: ConnectionId) {
+        self.user_channels.remove_connection(connection);
+        for app_id in self.registry.remove_connection(connection) {
+            log_info!(
+                "ApplicationID '{}' removed, connection closed",
+                id_to_string(&app_id)
+            );
+        }
+    }
+
+    /// Whether the application `app_id` or its context `context_id` is
+    /// registered on another channel than `connection`
+    fn registered_elsewhere(
+        &self,
+        app_id: &[u8; 4],
+        context_id: Option<&[u8; 4]>,
+        connection: Option<ConnectionId>,
+    ) -> bool {
+        let application = self
+            .registry
+            .application(app_id)
+            .is_some_and(|application| {
+                owned_by_other_channel(application.connection, application.pid, connection)
+            });
+        let context = context_id
+            .and_then(|context_id| self.registry.context(app_id, context_id))
+            .is_some_and(|context| {
+                owned_by_other_channel(context.connection, context.pid, connection)
+            });
+        if application || context {
+            log_warning!(
+                "ApplicationID '{}' is registered on another channel, request ignored",
+                id_to_string(app_id)
+            );
+        }
+        application || context
+    }
+
+    /// Process one message received from an application on `connection`, or
+    /// on the FIFO
+    pub fn handle_user_message(&mut self, message: UserMessage, connection: Option<ConnectionId>) {
+        match message {
+            UserMessage::Log(bytes) => {
+                self.forward_message(&DltMessage::new(bytes));
+            }
+            UserMessage::RegisterApplication(register, description) => {
+                if self.registered_elsewhere(&register.app_id, None, connection) {
+                    return;
+                }
+                let pid = register.pid;
+                let log_state = self.log_state();
+                let application = self.registry.register_application(
+                    register.app_id,
+                    pid,
+                    connection,
+                    description.clone(),
+                );
+                send_log_state(&self.user_channels, application, log_state);
+                let text = format!(
+                    "ApplicationID '{}' registered for PID {}, Description={}",
+                    id_to_string(&register.app_id),
+                    pid,
+                    description
+                );
+                log_info!("{}", text);
+                self.log_internal(&text);
+            }
+            UserMessage::UnRegisterApplication(unregister) => {
+                if self.registered_elsewhere(&unregister.app_id, None, connection) {
+                    return;
+                }
+                self.registry.unregister_application(&unregister.app_id);
+                let text = format!(
+                    "ApplicationID '{}' unregistered for PID {}",
+                    id_to_string(&unregister.app_id),
+                    { unregister.pid }
+                );
+                log_info!("{}", text);
+                self.log_internal(&text);
+            }
+            UserMessage::RegisterContext(register, description) => {
+                if self.registered_elsewhere(
+                    &register.app_id,
+                    Some(&register.context_id),
+                    connection,
+                ) {
+                    return;
+                }
+                let context =
+                    self.registry
+                        .register_context(&register, connection, description.clone());
+                send_log_level(&self.user_channels, context);
+                if self.config.send_context_registration {
+                    // announce the new context to the viewers
+                    let response = self.log_info(
+                        self.config.send_context_registration_option as u8,
+                        &register.app_id,
+                        &register.context_id,
+                    );
+                    self.forward_message(&response);
+                }
+                log_debug!(
+                    "ContextID '{}' registered for ApplicationID '{}', Description={}",
+                    id_to_string(&register.context_id),
+                    id_to_string(&register.app_id),
+                    description
+                );
+            }
+            UserMessage::UnRegisterContext(unregister) => {
+                if self.registered_elsewhere(
+                    &unregister.app_id,
+                    Some(&unregister.context_id),
+                    connection,
+                ) {
+                    return;
+                }
+                self.registry
+                    .unregister_context(&unregister.app_id, &unregister.context_id);
+                log_debug!(
+                    "ContextID '{}' unregistered for ApplicationID '{}'",
+                    id_to_string(&unregister.context_id),
+                    id_to_string(&unregister.app_id)
+                );
+            }
+            UserMessage::OverFlow(overflow) => {
+                let counter = overflow.overflow_counter;
+                log_warning!(
+                    "Buffer overflow in ApplicationID '{}', {} messages lost",
+                    id_to_string(&overflow.app_id),
+                    counter
+                );
+            }
+            UserMessage::AppLlTs(ll_ts) => {
+                if self.config.verbose {
+                    log_debug!(
+                        "Log level {} and trace status {} requested for ApplicationID '{}'",
+                        ll_ts.log_level,
+                        ll_ts.trace_status,
+                        id_to_string(&ll_ts.app_id)
+                    );
+                }
+            }
+            UserMessage::LogMode(log_mode) => {
+                if self.config.verbose {
+                    log_debug!("Log mode {} requested", log_mode.log_mode);
+                }
+            }
+            UserMessage::LogShm => {
+                #[cfg(feature = "shm")]
+                self.drain_shared_memory();
+            }
+            UserMessage::Marker => {}
+            UserMessage::LogLevel(_) | UserMessage::Injection(_, _) | UserMessage::LogState(_) => {
+                // these are only sent from the daemon to the applications
+                log_warning!("Unexpected message from application: {:?}", message);
+            }
+            UserMessage::NotSupported(message_type) => {
+                log_warning!("Unsupported user message type {}", message_type);
+            }
+        }
+    }
+}
+
+/// Read user messages from an application channel until it is closed.
+/// `connection` is the application connection the messages arrive on, `None`
+/// for the FIFO.
+pub(crate) async fn read_user_messages<R: Read + Unpin>(
+    mut reader: R,
+    daemon: SharedDaemon,
+    connection: Option<ConnectionId>,
+) {
+    let mut stream = UserMessageStream::new();
+    let mut buffer = vec![0u8; DLT_DAEMON_RCVBUFSIZE];
+    loop {
+        match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(len) => {
+                stream.feed(&buffer[..len]);
+                while let Some(message) = stream.next_message() {
+                    daemon
+                        .lock()
+                        .unwrap()
+                        .handle_user_message(message, connection);
+                }
+            }
+            Err(e) => {
+                log_error!("Error reading application messages: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Call `task` on the daemon every `interval`
+async fn every(interval: Duration, daemon: SharedDaemon, task: fn(&mut Daemon)) {
+    loop {
+        async_std::task::sleep(interval).await;
+        task(&mut daemon.lock().unwrap());
+    }
+}
+
+pub(crate) async fn run(
+    command_line: CommandLine,
+    config: DaemonConfig,
+    signals: Receiver<Signal>,
+) -> Result<(), DltError> {
+    // before the FIFO, applications look for it once they connected
+    #[cfg(feature = "shm")]
+    let shm = create_shared_memory(&config);
+    #[cfg(feature = "shm")]
+    let shm_path = Path::new(DLT_SHM_DIRECTORY).join(DLT_SHM_NAME.trim_start_matches('/'));
+    // restricted like the FIFO, an application holding the lock of the
+    // segment stalls the daemon
+    #[cfg(feature = "shm")]
+    if shm.is_some() && !config.daemon_fifo_group.is_empty() {
+        set_fifo_group(&shm_path, &config.daemon_fifo_group);
+    }
+    let fifo_path = config.fifo_directory.join(DLT_DAEMON_FIFO);
+    #[cfg(not(feature = "unix_socket"))]
+    let fifo = create_daemon_fifo(
+        &fifo_path,
+        config.daemon_fifo_size,
+        &config.daemon_fifo_group,
+    )?;
+    // the socket takes the place of the FIFO
+    #[cfg(feature = "unix_socket")]
+    let user_socket = create_user_socket(&fifo_path, &config.daemon_fifo_group)?;
+    let address = SocketAddr::new(config.bind_address, config.port);
+    let listener = TcpListener::bind(address).await?;
+    let control_socket = create_control_socket(&config.control_socket_path)?;
+    let ipc_tcp = match config.ipc_tcp_port {
+        Some(port) => {
+            match TcpListener::bind(SocketAddr::new(config.ipc_tcp_bind_address, port)).await {
+                Ok(listener) => Some(listener),
+                Err(e) => {
+                    log_error!("Cannot listen for applications on TCP port {}: {}", port, e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let ipc_vsock = match config.ipc_vsock_port {
+        Some(port) => match create_vsock_listener(port) {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                log_error!(
+                    "Cannot listen for applications on VSOCK port {}: {}",
+                    port,
+                    e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let control_socket_path = config.control_socket_path.clone();
+
+    // opened before dropping privileges, devices are usually owned by root
+    let serial = config.serial_port.as_ref().and_then(|serial_port| {
+        match open_serial_port(Path::new(serial_port), config.rs232_baudrate) {
+            Ok(serial) => Some((serial, serial_port.clone())),
+            Err(e) => {
+                log_error!("Cannot open serial device {}: {}", serial_port, e);
+                None
+            }
+        }
+    });
+
+    let pid_file = config.daemonize.then(|| config.pid_file.clone());
+    if let Some(pid_file) = &pid_file {
+        write_pid_file(pid_file)?;
+    }
+    let mut owned = vec![fifo_path.as_path(), control_socket_path.as_path()];
+    owned.extend(pid_file.as_deref());
+    #[cfg(feature = "shm")]
+    if shm.is_some() {
+        owned.push(&shm_path);
+    }
+    drop_privileges(
+        config.daemon_user.as_deref(),
+        config.daemon_group.as_deref(),
+        &owned,
+    )?;
+
+    let daemon = Arc::new(Mutex::new(Daemon::new(config)));
+    #[cfg(feature = "shm")]
+    if let Some(shm) = shm {
+        daemon.lock().unwrap().set_shared_memory(shm);
+    }
+
+    async_std::task::spawn(tcp_server(listener, daemon.clone()));
+    async_std::task::spawn(control_socket_server(control_socket, daemon.clone()));
+    if let Some((serial, serial_port)) = serial {
+        async_std::task::spawn(serial_client(serial, serial_port, daemon.clone()));
+    }
+    if !daemon.lock().unwrap().gateway().is_empty() {
+        async_std::task::spawn(run_gateway(daemon.clone()));
+    }
+
+    async_std::task::spawn(every(
+        DLT_DAEMON_HOUSEKEEPING_INTERVAL,
+        daemon.clone(),
+        Daemon::housekeeping,
+    ));
+    let (send_ecusoftware_version, send_timezone) = {
+        let daemon = daemon.lock().unwrap();
+        let config = daemon.config();
+        (config.send_ecusoftware_version, config.send_timezone)
+    };
+    if send_ecusoftware_version > 0 {
+        async_std::task::spawn(every(
+            Duration::from_secs(send_ecusoftware_version as u64),
+            daemon.clone(),
+            Daemon::send_software_version,
+        ));
+    }
+    if send_timezone > 0 {
+        async_std::task::spawn(every(
+            Duration::from_secs(send_timezone as u64),
+            daemon.clone(),
+            Daemon::send_timezone,
+        ));
+    }
+
+    #[cfg(not(feature = "unix_socket"))]
+    async_std::task::spawn(read_user_messages(
+        async_std::fs::File::from(fifo),
+        daemon.clone(),
+        None,
+    ));
+    #[cfg(feature = "unix_socket")]
+    async_std::task::spawn(user_socket_server(user_socket, daemon.clone()));
+    if let Some(listener) = ipc_tcp {
+        async_std::task::spawn(ipc_tcp_server(listener, daemon.clone()));
+    }
+    if let Some(listener) = ipc_vsock {
+        let daemon = daemon.clone();
+        std::thread::Builder::new()
+            .name("vsock".into())
+            .spawn(move || vsock_server(listener, daemon))?;
+    }
+
+    daemon
+        .lock()
+        .unwrap()
+        .log_internal("Daemon launched. Starting to output traces...");
+
+    while let Ok(signal) = signals.recv().await {
+        match signal {
+            Signal::Reload => {
+                log_notice!("Reloading configuration");
+                let config = command_line.load_config();
+                daemon.lock().unwrap().reload(config);
+            }
+            Signal::Terminate => break,
+        }
+    }
+
+    log_notice!("Exiting DLT daemon");
+    let writers = {
+        let mut daemon = daemon.lock().unwrap();
+        daemon.log_internal("Exiting DLT daemon...");
+        daemon.shutdown()
+    };
+    let flushed = async_std::future::timeout(DLT_DAEMON_SHUTDOWN_TIMEOUT, async {
+        for writer in writers {
+            writer.await;
+        }
+    })
+    .await;
+    if flushed.is_err() {
+        log_warning!("Not all clients received their messages");
+    }
+    let _ = std::fs::remove_file(&fifo_path);
+    let _ = std::fs::remove_file(&control_socket_path);
+    #[cfg(feature = "shm")]
+    let _ = SharedMemory::unlink(DLT_SHM_NAME);
+    if let Some(pid_file) = &pid_file {
+        remove_pid_file(pid_file);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_daemon, TestDir};
+    use async_std::net::TcpStream;
+    use dlt_user::user_header::{UserHeader, UserMessageType};
+    use libdlt::logstorage::DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME;
+
+    async fn start_tcp_server(daemon: &SharedDaemon) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        async_std::task::spawn(tcp_server(listener, daemon.clone()));
+        address
+    }
+
+    /// Read one DLT message from the client connection
+    async fn read_message(stream: &mut TcpStream) -> Vec<u8> {
+        let mut message = vec![0u8; 4];
+        stream.read_exact(&mut message).await.unwrap();
+        let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+        message.resize(len, 0);
+        stream.read_exact(&mut message[4..]).await.unwrap();
+        message
+    }
+
+    /// Read the status message of the daemon announcing the new client
+    async fn read_internal_message(stream: &mut TcpStream) {
+        let message = read_message(stream).await;
+        assert_eq!(&parse_header(&message).unwrap().app_id, b"DLTD");
+    }
+
+    /// A log message as written by an application
+    fn log_message(counter: u8) -> (Vec<u8>, Vec<u8>) {
+        let dlt_message = vec![0x20, counter, 0x00, 0x08, 1, 2, 3, 4];
+        let mut bytes = UserHeader::new(UserMessageType::Log).as_bytes().to_vec();
+        bytes.extend_from_slice(&dlt_message);
+        (bytes, dlt_message)
+    }
+
+    #[test]
+    fn forward_to_tcp_client() {
+        let dir = TestDir::new("forward");
+        async_std::task::block_on(async {
+            let daemon = test_daemon(&dir);
+            let address = start_tcp_server(&daemon).await;
+
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            // connection info announcing the new client
+            read_message(&mut stream).await;
+            read_internal_message(&mut stream).await;
+
+            let (bytes, dlt_message) = log_message(0);
+            read_user_messages(bytes.as_slice(), daemon.clone(), None).await;
+            assert_eq!(read_message(&mut stream).await, dlt_message);
+        });
+    }
+
+    #[test]
+    fn buffer_until_client_connects() {
+        let dir = TestDir::new("buffer");
+        async_std::task::block_on(async {
+            let daemon = test_daemon(&dir);
+            let address = start_tcp_server(&daemon).await;
+
+            let mut expected = Vec::new();
+            for counter in 0..3 {
+                let (bytes, dlt_message) = log_message(counter);
+                read_user_messages(bytes.as_slice(), daemon.clone(), None).await;
+                expected.push(dlt_message);
+            }
+
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            for dlt_message in expected {
+                assert_eq!(read_message(&mut stream).await, dlt_message);
+            }
+        });
+    }
+
+    #[test]
+    fn set_log_level() {
+        use async_std::io::WriteExt;
+
+        let dir = TestDir::new("control");
+        std::fs::create_dir_all(dir.join(crate::fifo::DLT_USER_DIR)).unwrap();
+        let pid = std::process::id();
+        let mut application_fifo =
+            crate::fifo::create_daemon_fifo(&crate::fifo::application_fifo(&dir, pid), 0, "")
+                .unwrap();
+        let daemon = test_daemon(&dir);
+        daemon.lock().unwrap().user_channels = UserChannels::new(Some(&dir));
+
+        let register = user_control_message::RegisterContext {
+            app_id: *b"APP1",
+            context_id: *b"CON1",
+            log_level_pos: 3,
+            log_level: -1,
+            trace_status: -1,
+            pid,
+            description_length: 0,
+        };
+        daemon
+            .lock()
+            .unwrap()
+            .handle_user_message(UserMessage::RegisterContext(register, String::new()), None);
+
+        async_std::task::block_on(async {
+            let address = start_tcp_server(&daemon).await;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            // the registration was announced while no client was connected
+            let log_info = read_message(&mut stream).await;
+            assert!(log_info.ends_with(b"\x07\x01\0APP1\x01\0CON1\x04\0\0\0\0\0remo"));
+            // connection info
+            read_message(&mut stream).await;
+            read_internal_message(&mut stream).await;
+
+            let request = crate::control::tests::control_request(
+                crate::control::DLT_SERVICE_ID_SET_LOG_LEVEL,
+                b"APP1CON1\x06remo",
+            );
+            stream.write_all(&request).await.unwrap();
+            let response = read_message(&mut stream).await;
+            // service id and status
+            assert!(response.ends_with(&[0x01, 0, 0, 0, DLT_SERVICE_RESPONSE_OK]));
+        });
+
+        // the level sent on registration and the changed one
+        let mut messages = UserMessageStream::new();
+        while messages.pending() < 2 * 14 {
+            let mut buffer = [0u8; 64];
+            let len = std::io::Read::read(&mut application_fifo, &mut buffer).unwrap();
+            messages.feed(&buffer[..len]);
+        }
+        let mut log_levels = Vec::new();
+        while let Some(UserMessage::LogLevel(log_level)) = messages.next_message() {
+            assert_eq!({ log_level.log_level_pos }, 3);
+            log_levels.push(log_level.log_level);
+        }
+        assert_eq!(log_levels, vec![4, 6]);
+    }
+
+    #[test]
+    fn inject_to_application() {
+        let dir = TestDir::new("inject");
+        std::fs::create_dir_all(dir.join(crate::fifo::DLT_USER_DIR)).unwrap();
+        let pid = std::process::id();
+        let mut application_fifo =
+            crate::fifo::create_daemon_fifo(&crate::fifo::application_fifo(&dir, pid), 0, "")
+                .unwrap();
+        let daemon = test_daemon(&dir);
+        daemon.lock().unwrap().user_channels = UserChannels::new(Some(&dir));
+        let register = user_control_message::RegisterContext {
+            app_id: *b"APP\0",
+            context_id: *b"CON\0",
+            log_level_pos: 5,
+            log_level: -1,
+            trace_status: -1,
+            pid,
+            description_length: 0,
+        };
+        daemon
+            .lock()
+            .unwrap()
+            .handle_user_message(UserMessage::RegisterContext(register, String::new()), None);
+
+        let injection = |daemon: &SharedDaemon| {
+            let request = crate::control::tests::control_request(0x1000, b"\x04\0\0\0test");
+            let response = daemon.lock().unwrap().client_message(&request).unwrap();
+            *response.as_bytes().last().unwrap()
+        };
+        assert_eq!(injection(&daemon), DLT_SERVICE_RESPONSE_OK);
+        daemon.lock().unwrap().config.injection_mode = false;
+        assert_eq!(injection(&daemon), DLT_SERVICE_RESPONSE_ERROR);
+
+        // the log level sent on registration, then the injection
+        let mut messages = UserMessageStream::new();
+        while messages.pending() < 14 + 24 {
+            let mut buffer = [0u8; 64];
+            let len = std::io::Read::read(&mut application_fifo, &mut buffer).unwrap();
+            messages.feed(&buffer[..len]);
+        }
+        assert!(matches!(
+            messages.next_message(),
+            Some(UserMessage::LogLevel(_))
+        ));
+        let Some(UserMessage::Injection(injection, data)) = messages.next_message() else {
+            panic!("injection expected");
+        };
+        assert_eq!({ injection.log_level_pos }, 5);
+        assert_eq!({ injection.service_id }, 0x1000);
+        assert_eq!(data, b"test");
+    }
+
+    #[test]
+    fn application_connection() {
+        use async_std::io::WriteExt;
+
+        let dir = TestDir::new("connection");
+        let daemon = test_daemon(&dir);
+        let pid = std::process::id();
+        let register = |app_id: &[u8; 4]| {
+            user_control_message::RegisterApplication {
+                app_id: *app_id,
+                pid,
+                description_length: 0,
+            }
+            .to_user_message(&[])
+        };
+        // a running local application
+        daemon.lock().unwrap().handle_user_message(
+            UserMessage::RegisterApplication(
+                user_control_message::RegisterApplication {
+                    app_id: *b"APP1",
+                    pid,
+                    description_length: 0,
+                },
+                String::new(),
+            ),
+            None,
+        );
+
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            async_std::task::spawn(ipc_tcp_server(listener, daemon.clone()));
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            // the same PID does not take over the local application
+            let mut request = register(b"APP1");
+            request.extend(register(b"APP2"));
+            stream.write_all(&request).await.unwrap();
+
+            let mut messages = UserMessageStream::new();
+            let mut buffer = [0u8; 64];
+            while messages.next_message().is_none() {
+                let len = stream.read(&mut buffer).await.unwrap();
+                messages.feed(&buffer[..len]);
+            }
+            {
+                let daemon = daemon.lock().unwrap();
+                assert_eq!(
+                    daemon.registry.application(b"APP1").unwrap().connection,
+                    None
+                );
+                assert!(daemon
+                    .registry
+                    .application(b"APP2")
+                    .unwrap()
+                    .connection
+                    .is_some());
+            }
+
+            // housekeeping does not check the PID of connected applications,
+            // they are removed when the connection is closed
+            drop(stream);
+            while daemon
+                .lock()
+                .unwrap()
+                .registry
+                .application(b"APP2")
+                .is_some()
+            {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(daemon
+                .lock()
+                .unwrap()
+                .registry
+                .application(b"APP1")
+                .is_some());
+        });
+    }
+
+    #[test]
+    fn force_context_log_level() {
+        let dir = TestDir::new("force");
+        let config = DaemonConfig {
+            persistance_storage_path: dir.to_path_buf(),
+            force_context_loglevel_and_tracestatus: true,
+            ..Default::default()
+        };
+        let mut daemon = Daemon::new(config);
+        let register = user_control_message::RegisterContext {
+            app_id: *b"APP1",
+            context_id: *b"CON1",
+            log_level_pos: 0,
+            log_level: DLT_LOG_VERBOSE,
+            trace_status: DLT_TRACE_STATUS_ON,
+            pid: 0,
+            description_length: 0,
+        };
+        daemon.handle_user_message(UserMessage::RegisterContext(register, String::new()), None);
+        // capped at the configured info level and trace status off
+        let context = daemon.registry.context(b"APP1", b"CON1").unwrap();
+        assert_eq!((context.log_level, context.trace_status), (4, 0));
+
+        let mut set_log_level = |log_level: i8| {
+            let response = daemon.control_response(ControlRequest::SetLogLevel {
+                app_id: *b"APP1",
+                context_id: *b"CON1",
+                log_level,
+            });
+            *response.as_bytes().last().unwrap()
+        };
+        assert_eq!(set_log_level(DLT_LOG_VERBOSE), DLT_SERVICE_RESPONSE_ERROR);
+        assert_eq!(set_log_level(3), DLT_SERVICE_RESPONSE_OK);
+        let response = daemon.control_response(ControlRequest::SetDefaultTraceStatus(1));
+        assert_eq!(
+            response.as_bytes().last(),
+            Some(&DLT_SERVICE_RESPONSE_ERROR)
+        );
+    }
+
+    #[test]
+    fn reload_keeps_startup_settings() {
+        let dir = TestDir::new("reload");
+        let daemon = test_daemon(&dir);
+        let mut daemon = daemon.lock().unwrap();
+        let config = DaemonConfig {
+            port: 4490,
+            verbose: true,
+            context_log_level: libdlt::config::DltLogLevel::DltLogDebug,
+            ..Default::default()
+        };
+        daemon.reload(config);
+        assert_eq!(daemon.config().port, 3490);
+        assert!(daemon.config().verbose);
+        assert_eq!(
+            daemon.config().context_log_level as i8,
+            libdlt::config::DltLogLevel::DltLogDebug as i8
+        );
+    }
+
+    #[test]
+    fn ecu_version_and_timezone() {
+        let dir = TestDir::new("version");
+        let version_file = dir.join("version");
+        std::fs::write(&version_file, "ECU-SW 1.2.3\n").unwrap();
+        let config = DaemonConfig {
+            tcpsync_serial_header: false,
+            persistance_storage_path: dir.to_path_buf(),
+            send_ecusoftware_version: 60,
+            path_to_ecusoftware_version: Some(version_file.clone()),
+            send_timezone: 60,
+            ..Default::default()
+        };
+        let daemon = Arc::new(Mutex::new(Daemon::new(config)));
+        async_std::task::block_on(async {
+            let address = start_tcp_server(&daemon).await;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            // connection info, then the version and the timezone
+            read_message(&mut stream).await;
+            let version = read_message(&mut stream).await;
+            assert!(version.ends_with(b"\x13\0\0\0\0\x0c\0\0\0ECU-SW 1.2.3"));
+            let timezone = read_message(&mut stream).await;
+            let (offset, is_dst) = local_timezone();
+            let mut payload = vec![0x03, 0x0F, 0, 0, DLT_SERVICE_RESPONSE_OK];
+            payload.extend_from_slice(&offset.to_le_bytes());
+            payload.push(is_dst as u8);
+            assert!(timezone.ends_with(&payload));
+            read_internal_message(&mut stream).await;
+
+            // periodic messages
+            daemon.lock().unwrap().send_software_version();
+            assert!(read_message(&mut stream).await.ends_with(b"ECU-SW 1.2.3"));
+        });
+    }
+
+    #[test]
+    fn shutdown_flushes_clients() {
+        let dir = TestDir::new("shutdown");
+        let daemon = test_daemon(&dir);
+        async_std::task::block_on(async {
+            let address = start_tcp_server(&daemon).await;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            read_message(&mut stream).await;
+            read_internal_message(&mut stream).await;
+
+            let (bytes, dlt_message) = log_message(0);
+            read_user_messages(bytes.as_slice(), daemon.clone(), None).await;
+            let writers = daemon.lock().unwrap().shutdown();
+            for writer in writers {
+                writer.await;
+            }
+            assert_eq!(read_message(&mut stream).await, dlt_message);
+        });
+        // the runtime configuration is stored in the configured directory
+        assert!(dir.join("dlt-runtime-context.cfg").exists());
+    }
+
+    #[test]
+    fn logstorage_device_besides_internal_storage() {
+        let dir = TestDir::new("logstorage");
+        for device in ["internal", "usb", "usb2"] {
+            std::fs::create_dir_all(dir.join(device)).unwrap();
+            std::fs::write(
+                dir.join(device)
+                    .join(DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME),
+                "[FILTER1]\nLogAppName=APP1\nLogLevel=DLT_LOG_INFO\nFile=app1\nFileSize=1000\n\
+                 NOFiles=1\n",
+            )
+            .unwrap();
+        }
+        let mut daemon = Daemon::new(DaemonConfig {
+            offline_logstorage_max_devices: 1,
+            offline_logstorage_dir_path: Some(dir.join("internal")),
+            persistance_storage_path: dir.to_path_buf(),
+            ..Default::default()
+        });
+        assert_eq!(daemon.logstorage.len(), 1);
+
+        let mut connect = |device: &str| {
+            let response = daemon.control_response(ControlRequest::OfflineLogstorage {
+                mount_point: dir.join(device),
+                connection_type: DLT_OFFLINE_LOGSTORAGE_DEVICE_CONNECTED,
+            });
+            *response.as_bytes().last().unwrap()
+        };
+        assert_eq!(connect("usb"), DLT_SERVICE_RESPONSE_OK);
+        // the only device slot is taken
+        assert_eq!(connect("usb2"), DLT_SERVICE_RESPONSE_ERROR);
+    }
+
+    #[test]
+    fn id_formatting() {
+        assert_eq!(id_to_string(b"APP1"), "APP1");
+        assert_eq!(id_to_string(b"AP\0\0"), "AP");
+    }
+
+    #[cfg(feature = "shm")]
+    #[test]
+    fn drain_shared_memory() {
+        let name = format!("/dlt-shm-daemon-{}", std::process::id());
+        let dir = TestDir::new("shm");
+        let daemon = test_daemon(&dir);
+        let mut daemon = daemon.lock().unwrap();
+        daemon.set_shared_memory(SharedMemory::create(&name, 1000).unwrap());
+        let application = SharedMemory::open(&name).unwrap();
+
+        let message = [0x20, 0, 0, 8, 1, 2, 3, 4];
+        assert_eq!(application.push(&message), Some(true));
+        // the length does not match the header
+        assert_eq!(application.push(&[0x20, 0, 0, 8, 1]), Some(false));
+        assert_eq!(application.push(&message), Some(false));
+        daemon.handle_user_message(UserMessage::LogShm, None);
+
+        let stored: Vec<DltMessage> = daemon.ring_buffer.drain().collect();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].as_bytes(), message);
+        assert_eq!(application.pop(), None);
+        SharedMemory::unlink(&name).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use crate::logging::{log_error, log_warning};
+use libdlt::error::DltError;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Files created by the daemon get no permissions for others
+const DLT_DAEMON_UMASK: libc::mode_t = 0o027;
+
+fn fork() -> Result<libc::pid_t, DltError> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error().into()),
+        pid => Ok(pid),
+    }
+}
+
+/// Detach from the terminal. Forks twice, so the daemon is not a session
+/// leader and can never acquire a controlling terminal again.
+///
+/// Must be called before any threads are started.
+pub(crate) fn daemonize() -> Result<(), DltError> {
+    if fork()? > 0 {
+        std::process::exit(0);
+    }
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if fork()? > 0 {
+        std::process::exit(0);
+    }
+
+    unsafe {
+        libc::umask(DLT_DAEMON_UMASK);
+    }
+    std::env::set_current_dir("/")?;
+
+    // standard descriptors point to /dev/null
+    let null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+/// Write the process id to `path`. Fails if the file names a process that is
+/// still running, which means another daemon is active.
+pub(crate) fn write_pid_file(path: &Path) -> Result<(), DltError> {
+    if let Ok(content) = fs::read_to_string(path) {
+        if let Ok(pid) = content.trim().parse::<libc::pid_t>() {
+            if pid != std::process::id() as libc::pid_t && unsafe { libc::kill(pid, 0) } == 0 {
+                log_warning!("DLT daemon already running with PID {}", pid);
+                return Err(DltError::DltReturnError);
+            }
+        }
+    }
+    fs::write(path, format!("{}\n", std::process::id()))?;
+    Ok(())
+}
+
+pub(crate) fn remove_pid_file(path: &Path) {
+    let _ = fs::remove_file(path);
+}
+
+fn to_cstring(value: &str) -> Result<CString, DltError> {
+    CString::new(value).map_err(|_| DltError::DltReturnWrongParameter)
+}
+
+/// Switch to `user` and `group`. The files in `owned` are handed over to the
+/// user first, so the daemon can still remove them on exit.
+pub(crate) fn drop_privileges(
+    user: Option<&str>,
+    group: Option<&str>,
+    owned: &[&Path],
+) -> Result<(), DltError> {
+    let uid = match user {
+        Some(user) => {
+            let passwd = unsafe { libc::getpwnam(to_cstring(user)?.as_ptr()) };
+            if passwd.is_null() {
+                log_warning!("User {} not found", user);
+                return Err(DltError::DltReturnWrongParameter);
+            }
+            Some(unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) })
+        }
+        None => None,
+    };
+    let gid = match group {
+        Some(group) => {
+            let entry = unsafe { libc::getgrnam(to_cstring(group)?.as_ptr()) };
+            if entry.is_null() {
+                log_warning!("Group {} not found", group);
+                return Err(DltError::DltReturnWrongParameter);
+            }
+            Some(unsafe { (*entry).gr_gid })
+        }
+        // the primary group of the user
+        None => uid.map(|(_, gid)| gid),
+    };
+
+    for path in owned {
+        let filename = to_cstring(&path.to_string_lossy())?;
+        let uid = uid.map(|(uid, _)| uid).unwrap_or(libc::uid_t::MAX);
+        if unsafe { libc::lchown(filename.as_ptr(), uid, libc::gid_t::MAX) } < 0 {
+            log_error!(
+                "Failed to change owner of {}: {}",
+                path.display(),
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    if let Some(gid) = gid {
+        if unsafe { libc::setgroups(0, std::ptr::null()) } < 0 || unsafe { libc::setgid(gid) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+    if let Some((uid, _)) = uid {
+        if unsafe { libc::setuid(uid) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_file() {
+        let path = std::env::temp_dir().join(format!("dlt-daemon-{}.pid", std::process::id()));
+        write_pid_file(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        // our own PID does not block writing the file again
+        write_pid_file(&path).unwrap();
+
+        // PID 1 is always running
+        fs::write(&path, "1\n").unwrap();
+        assert!(write_pid_file(&path).is_err());
+
+        remove_pid_file(&path);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unknown_user() {
+        assert!(drop_privileges(Some("no-such-dlt-user"), None, &[]).is_err());
+        assert!(drop_privileges(None, Some("no-such-dlt-group"), &[]).is_err());
+        // nothing to do
+        drop_privileges(None, None, &[]).unwrap();
+    }
+}
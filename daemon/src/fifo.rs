@@ -0,0 +1,160 @@
+// with the `unix_socket` feature applications connect to a socket instead
+#![cfg_attr(feature = "unix_socket", allow(dead_code))]
+
+use crate::logging::{log_error, log_warning};
+use libdlt::error::DltError;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::prelude::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Name of the FIFO the user applications write their messages to, it is
+/// created in the FIFO directory
+pub(crate) const DLT_DAEMON_FIFO: &str = "dlt";
+
+/// Directory below the FIFO directory holding the FIFOs of the applications
+pub(crate) const DLT_USER_DIR: &str = "dltpipes";
+
+// S_IRUSR | S_IWUSR | S_IWGRP, same as the C daemon
+const DLT_DAEMON_FIFO_MODE: libc::mode_t = 0o620;
+
+fn path_to_cstring(path: &Path) -> Result<CString, DltError> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| DltError::DltReturnWrongParameter)
+}
+
+/// Create the daemon FIFO at `path` and open it for reading.
+///
+/// The FIFO is opened read-write, so that the daemon does not see end of file
+/// when the last application closes its end. If `group` is not empty the FIFO
+/// is handed over to that group, so applications of the group can write to it.
+pub(crate) fn create_daemon_fifo(path: &Path, size: u32, group: &str) -> Result<File, DltError> {
+    let filename = path_to_cstring(path)?;
+
+    // remove a stale FIFO from a previous run
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    if unsafe { libc::mkfifo(filename.as_ptr(), DLT_DAEMON_FIFO_MODE) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    // the mode passed to mkfifo is restricted by the umask
+    fs::set_permissions(path, fs::Permissions::from_mode(DLT_DAEMON_FIFO_MODE))?;
+
+    if !group.is_empty() {
+        set_fifo_group(path, group);
+    }
+
+    let fifo = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_CLOEXEC)
+        .open(path)?;
+
+    set_pipe_size(&fifo, size);
+
+    Ok(fifo)
+}
+
+/// Path of the FIFO the application with `pid` reads daemon messages from
+pub(crate) fn application_fifo(fifo_directory: &Path, pid: u32) -> PathBuf {
+    fifo_directory.join(DLT_USER_DIR).join(format!("dlt{pid}"))
+}
+
+/// Write a message to the FIFO of an application. Never blocks, a full FIFO
+/// is reported as an error.
+pub(crate) fn send_to_application(fifo_directory: &Path, pid: u32, data: &[u8]) -> io::Result<()> {
+    let mut fifo = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+        .open(application_fifo(fifo_directory, pid))?;
+    fifo.write_all(data)
+}
+
+/// Hand `path` over to `group`, so applications of the group can write to it
+pub(crate) fn set_fifo_group(path: &Path, group: &str) {
+    let Ok(filename) = path_to_cstring(path) else {
+        return;
+    };
+    let Ok(group_name) = CString::new(group) else {
+        log_warning!("Invalid FIFO group {group}");
+        return;
+    };
+    let group_entry = unsafe { libc::getgrnam(group_name.as_ptr()) };
+    if group_entry.is_null() {
+        log_warning!("Group {group} not found, FIFO group not changed");
+        return;
+    }
+    let gid = unsafe { (*group_entry).gr_gid };
+    if unsafe { libc::chown(filename.as_ptr(), libc::uid_t::MAX, gid) } < 0 {
+        log_error!(
+            "Failed to change FIFO group to {group}: {}",
+            io::Error::last_os_error()
+        );
+    }
+}
+
+fn set_pipe_size(fifo: &File, size: u32) {
+    use std::os::unix::io::AsRawFd;
+
+    if size == 0 {
+        return;
+    }
+    let result = unsafe { libc::fcntl(fifo.as_raw_fd(), libc::F_SETPIPE_SZ, size as libc::c_int) };
+    if result < 0 {
+        log_error!(
+            "Failed to set FIFO size to {size}: {}",
+            io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use std::io::Read;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn create_fifo() {
+        let dir = TestDir::new("fifo");
+        let path = dir.join("dlt");
+        let mut fifo = create_daemon_fifo(&path, 131072, "").unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_fifo());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o620);
+        let size = unsafe { libc::fcntl(fifo.as_raw_fd(), libc::F_GETPIPE_SZ) };
+        assert!(size >= 131072);
+
+        let mut writer = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        writer.write_all(b"DUH\x01").unwrap();
+        let mut buffer = [0u8; 4];
+        fifo.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"DUH\x01");
+
+        // creating it again replaces the stale FIFO
+        create_daemon_fifo(&path, 0, "").unwrap();
+    }
+
+    #[test]
+    fn write_to_application() {
+        let dir = TestDir::new("application");
+        fs::create_dir_all(dir.join(DLT_USER_DIR)).unwrap();
+        let path = application_fifo(&dir, 42);
+        assert_eq!(path, dir.join("dltpipes/dlt42"));
+
+        // no reader, the application is gone
+        assert!(send_to_application(&dir, 42, b"DUH\x01").is_err());
+
+        let mut fifo = create_daemon_fifo(&path, 0, "").unwrap();
+        send_to_application(&dir, 42, b"DUH\x01").unwrap();
+        let mut buffer = [0u8; 4];
+        fifo.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"DUH\x01");
+    }
+}
@@ -0,0 +1,564 @@
+use crate::control::{
+    DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL, DLT_SERVICE_ID_GET_LOG_INFO,
+    DLT_SERVICE_ID_GET_SOFTWARE_VERSION,
+};
+use crate::daemon::{id_to_string, SharedDaemon};
+use crate::logging::{log_error, log_info, log_warning};
+use crate::message::{DltMessage, MessageStream, DLT_SERIAL_HEADER};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{SocketAddr, TcpStream};
+use libdlt::config::DaemonConfig;
+use libdlt::gateway::{ConnectTrigger, GatewayConfig, PassiveNodeConfig};
+use std::net::Shutdown;
+use std::time::Duration;
+
+/// Messages queued for a passive node before further messages are dropped
+const DLT_GATEWAY_QUEUE_SIZE: usize = 100;
+
+/// GetLogInfo options requesting log levels, trace status and descriptions
+const DLT_GATEWAY_GET_LOG_INFO_OPTIONS: u8 = 7;
+
+// Passive node states reported in connection status responses
+const DLT_GATEWAY_INITIALIZED: u8 = 1;
+const DLT_GATEWAY_CONNECTED: u8 = 2;
+const DLT_GATEWAY_DISCONNECTED: u8 = 3;
+
+enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected {
+        /// Messages sent to the node are queued here
+        sender: Sender<Vec<u8>>,
+        stream: TcpStream,
+    },
+}
+
+struct PassiveNode {
+    config: PassiveNodeConfig,
+    ecu_id: [u8; 4],
+    state: ConnectionState,
+    /// The node should be connected
+    enabled: bool,
+    /// Failed connection attempts since the node was enabled
+    retries: u32,
+    /// Seconds since the connection was established
+    connected_for: u32,
+}
+
+impl PassiveNode {
+    /// Queue a message for the node, false if it is not connected
+    fn send(&self, message: &[u8]) -> bool {
+        let ConnectionState::Connected { sender, .. } = &self.state else {
+            return false;
+        };
+        let mut data = Vec::with_capacity(DLT_SERIAL_HEADER.len() + message.len());
+        if self.config.send_serial_header {
+            data.extend_from_slice(&DLT_SERIAL_HEADER);
+        }
+        data.extend_from_slice(message);
+        if sender.try_send(data).is_err() {
+            log_warning!(
+                "Passive node {} does not keep up, message dropped",
+                self.config.ecu_id
+            );
+        }
+        true
+    }
+
+    /// Send a control request configured in `SendControl` or `SendPeriodicControl`
+    fn send_control(&self, service_id: u32) {
+        let payload = match service_id {
+            DLT_SERVICE_ID_GET_LOG_INFO => {
+                let mut payload = vec![DLT_GATEWAY_GET_LOG_INFO_OPTIONS];
+                // all applications and contexts
+                payload.extend_from_slice(&[0; 8]);
+                payload.extend_from_slice(b"remo");
+                payload
+            }
+            DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL | DLT_SERVICE_ID_GET_SOFTWARE_VERSION => {
+                Vec::new()
+            }
+            _ => {
+                log_warning!(
+                    "Control message 0x{:02X} cannot be sent to passive node {}",
+                    service_id,
+                    self.config.ecu_id
+                );
+                return;
+            }
+        };
+        let message = DltMessage::control_request(&self.config.ecu_id, service_id, payload);
+        self.send(message.as_bytes());
+    }
+
+    /// Close the connection to the node
+    fn shutdown(&self, stream: &TcpStream) {
+        if let Err(e) = stream.shutdown(Shutdown::Both) {
+            log_error!(
+                "Failed to disconnect passive node {}: {}",
+                self.config.ecu_id,
+                e
+            );
+        }
+    }
+}
+
+/// Connections to the passive nodes of a gateway. The daemon forwards the
+/// messages of the passive nodes to its clients and the control requests of
+/// its clients addressed to a passive node to that node.
+#[derive(Default)]
+pub(crate) struct Gateway {
+    /// Seconds between connection attempts and periodic control messages
+    interval: u32,
+    nodes: Vec<PassiveNode>,
+}
+
+impl Gateway {
+    /// Load the passive nodes if the daemon runs in gateway mode
+    pub fn new(config: &DaemonConfig) -> Self {
+        if !config.gateway_mode {
+            return Gateway::default();
+        }
+        let path = config.gateway_config_file.to_string_lossy();
+        match GatewayConfig::from_file(&path) {
+            Ok(gateway_config) => Gateway::from_config(gateway_config, &config.ecu_id),
+            Err(e) => {
+                log_error!("Cannot load gateway configuration {}: {}", path, e);
+                Gateway::default()
+            }
+        }
+    }
+
+    fn from_config(config: GatewayConfig, ecu_id: &str) -> Self {
+        for skipped in &config.skipped {
+            log_warning!("{}", skipped);
+        }
+        let nodes = config
+            .passive_nodes
+            .into_iter()
+            .filter(|node| {
+                if node.ecu_id == ecu_id {
+                    log_warning!("Passive node {} uses the ECU id of the gateway", node.name);
+                }
+                node.ecu_id != ecu_id
+            })
+            .map(|node| {
+                let mut id = [0u8; 4];
+                id[..node.ecu_id.len()].copy_from_slice(node.ecu_id.as_bytes());
+                PassiveNode {
+                    ecu_id: id,
+                    state: ConnectionState::Disconnected,
+                    enabled: node.connect == ConnectTrigger::OnStartup,
+                    retries: 0,
+                    connected_for: 0,
+                    config: node,
+                }
+            })
+            .collect();
+        Gateway {
+            interval: config.interval,
+            nodes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn is_passive_node(&self, ecu_id: &[u8; 4]) -> bool {
+        self.nodes.iter().any(|node| node.ecu_id == *ecu_id)
+    }
+
+    /// Forward a message to a passive node, false if it is not connected
+    pub fn send(&self, ecu_id: &[u8; 4], message: &[u8]) -> bool {
+        self.nodes
+            .iter()
+            .find(|node| node.ecu_id == *ecu_id)
+            .is_some_and(|node| node.send(message))
+    }
+
+    /// Connect a passive node on request, it is connected with the next
+    /// interval. False if the node is unknown.
+    pub fn connect(&mut self, ecu_id: &[u8; 4]) -> bool {
+        let Some(node) = self.node_mut(ecu_id) else {
+            return false;
+        };
+        node.enabled = true;
+        node.retries = 0;
+        true
+    }
+
+    /// Close the connection to a passive node, false if it is neither
+    /// connected nor connecting
+    pub fn disconnect(&mut self, ecu_id: &[u8; 4]) -> bool {
+        let Some(node) = self.node_mut(ecu_id) else {
+            return false;
+        };
+        node.enabled = false;
+        match &node.state {
+            // the connection task notices the closed stream and updates the state
+            ConnectionState::Connected { stream, .. } => node.shutdown(stream),
+            // closed as soon as it is established
+            ConnectionState::Connecting => {}
+            ConnectionState::Disconnected => return false,
+        }
+        true
+    }
+
+    /// ECU id and connection state of all passive nodes
+    pub fn connection_status(&self) -> Vec<([u8; 4], u8)> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let status = match node.state {
+                    ConnectionState::Disconnected => DLT_GATEWAY_DISCONNECTED,
+                    ConnectionState::Connecting => DLT_GATEWAY_INITIALIZED,
+                    ConnectionState::Connected { .. } => DLT_GATEWAY_CONNECTED,
+                };
+                (node.ecu_id, status)
+            })
+            .collect()
+    }
+
+    fn node_mut(&mut self, ecu_id: &[u8; 4]) -> Option<&mut PassiveNode> {
+        self.nodes.iter_mut().find(|node| node.ecu_id == *ecu_id)
+    }
+
+    /// Called every interval. Sends the periodic control messages and returns
+    /// the nodes to connect to.
+    fn tick(&mut self) -> Vec<([u8; 4], SocketAddr)> {
+        let interval = self.interval;
+        let mut connect = Vec::new();
+        for node in self.nodes.iter_mut() {
+            match node.state {
+                ConnectionState::Connected { .. } => {
+                    for (service_id, period) in &node.config.send_periodic_control {
+                        if node.connected_for % period < interval {
+                            node.send_control(*service_id);
+                        }
+                    }
+                    node.connected_for += interval;
+                }
+                ConnectionState::Disconnected if node.enabled => {
+                    if node.retries * interval >= node.config.timeout {
+                        log_warning!(
+                            "Connection to passive node {} timed out",
+                            node.config.ecu_id
+                        );
+                        node.enabled = false;
+                        continue;
+                    }
+                    node.retries += 1;
+                    node.state = ConnectionState::Connecting;
+                    connect.push((
+                        node.ecu_id,
+                        SocketAddr::new(node.config.ip_address, node.config.port),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        connect
+    }
+
+    fn connected(&mut self, ecu_id: &[u8; 4], sender: Sender<Vec<u8>>, stream: TcpStream) {
+        if let Some(node) = self.node_mut(ecu_id) {
+            if !node.enabled {
+                // disconnected on request while connecting
+                node.shutdown(&stream);
+                return;
+            }
+            log_info!("Passive node {} connected", node.config.ecu_id);
+            node.state = ConnectionState::Connected { sender, stream };
+            node.retries = 0;
+            node.connected_for = 0;
+            for service_id in &node.config.send_control {
+                node.send_control(*service_id);
+            }
+        }
+    }
+
+    fn disconnected(&mut self, ecu_id: &[u8; 4]) {
+        if let Some(node) = self.node_mut(ecu_id) {
+            if matches!(node.state, ConnectionState::Connected { .. }) {
+                log_info!("Passive node {} disconnected", node.config.ecu_id);
+            }
+            node.state = ConnectionState::Disconnected;
+        }
+    }
+}
+
+/// Connect to the passive nodes and send their periodic control messages
+pub(crate) async fn run_gateway(daemon: SharedDaemon) {
+    let interval = Duration::from_secs(daemon.lock().unwrap().gateway().interval as u64);
+    loop {
+        let connect = daemon.lock().unwrap().gateway_mut().tick();
+        for (ecu_id, address) in connect {
+            async_std::task::spawn(connect_passive_node(daemon.clone(), ecu_id, address));
+        }
+        async_std::task::sleep(interval).await;
+    }
+}
+
+async fn connect_passive_node(daemon: SharedDaemon, ecu_id: [u8; 4], address: SocketAddr) {
+    let stream = match TcpStream::connect(address).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log_error!(
+                "Cannot connect to passive node {} at {}: {}",
+                id_to_string(&ecu_id),
+                address,
+                e
+            );
+            daemon.lock().unwrap().gateway_mut().disconnected(&ecu_id);
+            return;
+        }
+    };
+    let (sender, receiver) = channel::bounded(DLT_GATEWAY_QUEUE_SIZE);
+    let writer = async_std::task::spawn(write_to_passive_node(stream.clone(), receiver));
+    daemon
+        .lock()
+        .unwrap()
+        .gateway_mut()
+        .connected(&ecu_id, sender, stream.clone());
+
+    let mut reader = stream;
+    let mut messages = MessageStream::new();
+    let mut buffer = [0u8; 4096];
+    while let Ok(len) = reader.read(&mut buffer).await {
+        if len == 0 {
+            break;
+        }
+        messages.feed(&buffer[..len]);
+        let mut daemon = daemon.lock().unwrap();
+        while let Some(message) = messages.next_message() {
+            daemon.forward_message(&DltMessage::new(message));
+        }
+    }
+
+    daemon.lock().unwrap().gateway_mut().disconnected(&ecu_id);
+    writer.cancel().await;
+}
+
+async fn write_to_passive_node(mut stream: TcpStream, receiver: Receiver<Vec<u8>>) {
+    while let Ok(data) = receiver.recv().await {
+        if let Err(e) = stream.write_all(&data).await {
+            log_error!("Failed to send to passive node: {}", e);
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tcp_server;
+    use crate::control::tests::control_request;
+    use crate::control::{
+        DLT_GATEWAY_CONNECT, DLT_GATEWAY_DISCONNECT, DLT_SERVICE_ID_PASSIVE_NODE_CONNECT,
+        DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS, DLT_SERVICE_RESPONSE_ERROR,
+    };
+    use crate::control_socket::{control_socket_server, create_control_socket};
+    use crate::daemon::Daemon;
+    use crate::message::{parse_header, DLT_SERVICE_RESPONSE_OK};
+    use crate::test_util::TestDir;
+    use async_std::net::TcpListener;
+    use async_std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+
+    async fn read_message<R: ReadExt + Unpin>(stream: &mut R) -> Vec<u8> {
+        let mut message = vec![0u8; 4];
+        stream.read_exact(&mut message).await.unwrap();
+        let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+        message.resize(len, 0);
+        stream.read_exact(&mut message[4..]).await.unwrap();
+        message
+    }
+
+    /// The ECU id and service id of a control message
+    fn ids(message: &[u8]) -> ([u8; 4], u32) {
+        let header = parse_header(message).unwrap();
+        let offset = header.payload_offset;
+        let service_id = u32::from_le_bytes(message[offset..offset + 4].try_into().unwrap());
+        (header.ecu_id.unwrap(), service_id)
+    }
+
+    #[test]
+    fn forward_passive_node_messages() {
+        let dir = TestDir::new("gateway");
+        async_std::task::block_on(async {
+            let passive = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let config = GatewayConfig::from_string(&format!(
+                "[PassiveNode1]\nIPaddress=127.0.0.1\nPort={}\nEcuID=ECU2\n\
+                 SendControl=0x13\nSendPeriodicControl=0x04:1\n",
+                passive.local_addr().unwrap().port()
+            ))
+            .unwrap();
+            let mut daemon = Daemon::new(DaemonConfig {
+                tcpsync_serial_header: false,
+                persistance_storage_path: dir.to_path_buf(),
+                ..Default::default()
+            });
+            *daemon.gateway_mut() = Gateway::from_config(config, "ECU1");
+            let daemon = Arc::new(Mutex::new(daemon));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            async_std::task::spawn(tcp_server(listener, daemon.clone()));
+            let mut viewer = TcpStream::connect(address).await.unwrap();
+            // connection info and the status message of the daemon
+            read_message(&mut viewer).await;
+            read_message(&mut viewer).await;
+
+            async_std::task::spawn(run_gateway(daemon.clone()));
+            let (mut node, _) = passive.accept().await.unwrap();
+            // configured control messages, addressed to the passive node
+            assert_eq!(
+                ids(&read_message(&mut node).await),
+                (*b"ECU2", DLT_SERVICE_ID_GET_SOFTWARE_VERSION)
+            );
+            assert_eq!(
+                ids(&read_message(&mut node).await),
+                (*b"ECU2", DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL)
+            );
+
+            // messages of the passive node keep its ECU id
+            let log_message = DltMessage::control_response("ECU2", 0x13, vec![0]);
+            node.write_all(log_message.as_bytes()).await.unwrap();
+            assert_eq!(read_message(&mut viewer).await, log_message.as_bytes());
+
+            // requests for the passive node are forwarded to it
+            let request =
+                DltMessage::control_request("ECU2", 0x03, b"\x07APP1\0\0\0\0remo".to_vec());
+            viewer.write_all(request.as_bytes()).await.unwrap();
+            let mut forwarded = read_message(&mut node).await;
+            while ids(&forwarded).1 == DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL {
+                forwarded = read_message(&mut node).await;
+            }
+            assert_eq!(forwarded, request.as_bytes());
+
+            // other requests are handled by the gateway
+            viewer
+                .write_all(&control_request(DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL, &[]))
+                .await
+                .unwrap();
+            let response = read_message(&mut viewer).await;
+            assert_eq!(
+                ids(&response),
+                (*b"ECU1", DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL)
+            );
+        });
+    }
+
+    #[test]
+    fn passive_node_control() {
+        let dir = TestDir::new("gateway-ctrl");
+        async_std::task::block_on(async {
+            let passive = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let config = GatewayConfig::from_string(&format!(
+                "[PassiveNode1]\nIPaddress=127.0.0.1\nPort={}\nEcuID=ECU2\nConnect=OnDemand\n",
+                passive.local_addr().unwrap().port()
+            ))
+            .unwrap();
+            let mut daemon = Daemon::new(DaemonConfig {
+                persistance_storage_path: dir.to_path_buf(),
+                ..Default::default()
+            });
+            *daemon.gateway_mut() = Gateway::from_config(config, "ECU1");
+            let daemon = Arc::new(Mutex::new(daemon));
+            async_std::task::spawn(run_gateway(daemon.clone()));
+
+            let socket = dir.join("dlt-ctrl.sock");
+            let listener = create_control_socket(&socket).unwrap();
+            async_std::task::spawn(control_socket_server(listener, daemon.clone()));
+            let mut control = UnixStream::connect(&socket).await.unwrap();
+            let connect = |node_id: &[u8; 4], connection_status: u32| {
+                let mut parameters = connection_status.to_le_bytes().to_vec();
+                parameters.extend_from_slice(node_id);
+                control_request(DLT_SERVICE_ID_PASSIVE_NODE_CONNECT, &parameters)
+            };
+            let status_request =
+                control_request(DLT_SERVICE_ID_PASSIVE_NODE_CONNECTION_STATUS, &[]);
+
+            // number of nodes, then the status and id of the first node
+            control.write_all(&status_request).await.unwrap();
+            let status = read_message(&mut control).await;
+            let offset = parse_header(&status).unwrap().payload_offset;
+            assert_eq!(status[offset + 4], DLT_SERVICE_RESPONSE_OK);
+            assert_eq!(&status[offset + 5..offset + 9], &1u32.to_le_bytes());
+            assert_eq!(status[offset + 9], DLT_GATEWAY_DISCONNECTED);
+            assert_eq!(&status[offset + 9 + 255..offset + 9 + 259], b"ECU2");
+
+            control
+                .write_all(&connect(b"ECU2", DLT_GATEWAY_CONNECT))
+                .await
+                .unwrap();
+            let response = read_message(&mut control).await;
+            assert_eq!(response.last(), Some(&DLT_SERVICE_RESPONSE_OK));
+            let (mut node, _) = passive.accept().await.unwrap();
+            while !daemon
+                .lock()
+                .unwrap()
+                .gateway()
+                .connection_status()
+                .contains(&(*b"ECU2", DLT_GATEWAY_CONNECTED))
+            {
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+
+            control
+                .write_all(&connect(b"ECU2", DLT_GATEWAY_DISCONNECT))
+                .await
+                .unwrap();
+            let response = read_message(&mut control).await;
+            assert_eq!(response.last(), Some(&DLT_SERVICE_RESPONSE_OK));
+            // the gateway closed the connection
+            let mut buffer = [0u8; 16];
+            assert_eq!(node.read(&mut buffer).await.unwrap(), 0);
+
+            control
+                .write_all(&connect(b"ECU3", DLT_GATEWAY_CONNECT))
+                .await
+                .unwrap();
+            let response = read_message(&mut control).await;
+            assert_eq!(response.last(), Some(&DLT_SERVICE_RESPONSE_ERROR));
+        });
+    }
+
+    #[test]
+    fn disconnect_while_connecting() {
+        async_std::task::block_on(async {
+            let passive = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let config = GatewayConfig::from_string(&format!(
+                "[PassiveNode1]\nIPaddress=127.0.0.1\nPort={}\nEcuID=ECU2\nConnect=OnDemand\n",
+                passive.local_addr().unwrap().port()
+            ))
+            .unwrap();
+            let mut gateway = Gateway::from_config(config, "ECU1");
+            assert!(!gateway.disconnect(b"ECU2"));
+            assert!(gateway.connect(b"ECU2"));
+            assert_eq!(gateway.tick().len(), 1);
+            assert!(gateway.disconnect(b"ECU2"));
+
+            // the connection in progress is closed once it is established
+            let stream = TcpStream::connect(passive.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (mut node, _) = passive.accept().await.unwrap();
+            let (sender, _receiver) = channel::bounded(1);
+            gateway.connected(b"ECU2", sender, stream);
+            assert_eq!(
+                gateway.connection_status(),
+                vec![(*b"ECU2", DLT_GATEWAY_INITIALIZED)]
+            );
+            let mut buffer = [0u8; 16];
+            assert_eq!(node.read(&mut buffer).await.unwrap(), 0);
+            gateway.disconnected(b"ECU2");
+            assert_eq!(
+                gateway.connection_status(),
+                vec![(*b"ECU2", DLT_GATEWAY_DISCONNECTED)]
+            );
+        });
+    }
+}
@@ -0,0 +1,156 @@
+use libdlt::config::{DaemonLoggingMode, LogLevel};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::log(libdlt::config::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_warning {
+    ($($arg:tt)*) => {
+        $crate::logging::log(libdlt::config::LogLevel::Warning, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_notice {
+    ($($arg:tt)*) => {
+        $crate::logging::log(libdlt::config::LogLevel::Notice, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::log(libdlt::config::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logging::log(libdlt::config::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {log_debug, log_error, log_info, log_notice, log_warning};
+
+/// Where the diagnostics of the daemon go, see `LoggingMode`
+enum Output {
+    Stdout,
+    Stderr,
+    Syslog,
+    File(File),
+}
+
+struct Logger {
+    output: Output,
+    /// Messages less severe than this are dropped
+    level: LogLevel,
+}
+
+/// Until the configuration is loaded everything is printed
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    output: Output::Stdout,
+    level: LogLevel::Debug,
+});
+
+fn prefix(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Emergency => "EMERGENCY",
+        LogLevel::Alert => "ALERT",
+        LogLevel::Critical => "CRITICAL",
+        LogLevel::Error => "ERROR",
+        LogLevel::Warning => "WARNING",
+        LogLevel::Notice => "NOTICE",
+        LogLevel::Info => "INFO",
+        LogLevel::Debug => "DEBUG",
+    }
+}
+
+impl Logger {
+    fn new(mode: &DaemonLoggingMode, level: LogLevel) -> Self {
+        let output = match mode {
+            DaemonLoggingMode::Stdout => Output::Stdout,
+            DaemonLoggingMode::StdError => Output::Stderr,
+            DaemonLoggingMode::Syslog => {
+                unsafe { libc::openlog(c"DLT".as_ptr(), libc::LOG_PID, libc::LOG_DAEMON) };
+                Output::Syslog
+            }
+            DaemonLoggingMode::File(path) => {
+                match OpenOptions::new().create(true).append(true).open(path) {
+                    Ok(file) => Output::File(file),
+                    Err(e) => {
+                        log_error!("Cannot open log file {}: {}", path.display(), e);
+                        Output::Stdout
+                    }
+                }
+            }
+        };
+        Logger { output, level }
+    }
+
+    fn log(&mut self, level: LogLevel, args: fmt::Arguments) {
+        if level > self.level {
+            return;
+        }
+        if let Output::Syslog = self.output {
+            if let Ok(message) = std::ffi::CString::new(args.to_string()) {
+                // the priorities of syslog are in the same order
+                unsafe { libc::syslog(level as libc::c_int, c"%s".as_ptr(), message.as_ptr()) };
+            }
+            return;
+        }
+        // same format as the C daemon
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format!(
+            "[{:5}.{:06}]~DLT~{:5}~{:<10}| {}\n",
+            time.as_secs(),
+            time.subsec_micros(),
+            std::process::id(),
+            prefix(level),
+            args
+        );
+        let _ = match &mut self.output {
+            Output::Stdout => std::io::stdout().write_all(line.as_bytes()),
+            Output::Stderr => std::io::stderr().write_all(line.as_bytes()),
+            Output::File(file) => file.write_all(line.as_bytes()),
+            Output::Syslog => Ok(()),
+        };
+    }
+}
+
+/// Send the diagnostics to the output configured with `LoggingMode` and
+/// drop the ones less severe than `LoggingLevel`
+pub(crate) fn init_logging(mode: &DaemonLoggingMode, level: LogLevel) {
+    let logger = Logger::new(mode, level);
+    *LOGGER.lock().unwrap() = logger;
+}
+
+/// Write a diagnostic message, use the `log_*` macros instead
+pub(crate) fn log(level: LogLevel, args: fmt::Arguments) {
+    LOGGER.lock().unwrap().log(level, args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_to_file() {
+        let path = std::env::temp_dir().join(format!("dlt-daemon-log-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut logger = Logger::new(&DaemonLoggingMode::File(path.clone()), LogLevel::Warning);
+        logger.log(LogLevel::Warning, format_args!("written {}", 1));
+        logger.log(LogLevel::Info, format_args!("dropped"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.ends_with("~WARNING   | written 1\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,525 @@
+use crate::daemon::id_to_string;
+use crate::logging::{log_error, log_warning};
+use crate::message::{storage_header, DltMessage, HeaderInfo, DLT_TYPE_LOG};
+use crate::offline_trace::file_timestamp;
+use libdlt::config::DaemonConfig;
+use libdlt::error::DltError;
+use libdlt::logstorage::{
+    LogstorageConfig, LogstorageFilter, DLT_LOGSTORAGE_SYNC_ON_DEMAND, DLT_LOGSTORAGE_SYNC_ON_MSG,
+    DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE, DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME,
+};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const DLT_OFFLINE_LOGSTORAGE_FILE_EXTENSION: &str = ".dlt";
+
+/// Names of the log files: `<File><delimiter><index>[<delimiter><timestamp>].dlt`.
+/// The index counts from 1 to `max_counter` and then starts over.
+#[derive(Clone, Debug)]
+struct FileNaming {
+    delimiter: String,
+    timestamp: bool,
+    max_counter: u32,
+}
+
+impl FileNaming {
+    fn from_config(config: &DaemonConfig) -> Self {
+        FileNaming {
+            delimiter: config.offline_logstorage_delimiter.clone(),
+            timestamp: config.offline_logstorage_timestamp,
+            max_counter: config.offline_logstorage_max_counter.max(1),
+        }
+    }
+
+    fn name(&self, base: &str, index: u32) -> String {
+        let mut name = if self.max_counter == u32::MAX {
+            format!("{base}{}{index}", self.delimiter)
+        } else {
+            let width = self.max_counter.to_string().len();
+            format!("{base}{}{index:0width$}", self.delimiter)
+        };
+        if self.timestamp {
+            name.push_str(&self.delimiter);
+            name.push_str(&file_timestamp());
+        }
+        name.push_str(DLT_OFFLINE_LOGSTORAGE_FILE_EXTENSION);
+        name
+    }
+
+    /// Index of a log file of `base`, `None` if the file belongs to another filter
+    fn index(&self, base: &str, name: &str) -> Option<u32> {
+        let rest = name
+            .strip_prefix(base)?
+            .strip_prefix(self.delimiter.as_str())?
+            .strip_suffix(DLT_OFFLINE_LOGSTORAGE_FILE_EXTENSION)?;
+        let index = match self.timestamp {
+            true => rest.split(self.delimiter.as_str()).next()?,
+            false => rest,
+        };
+        index.parse().ok()
+    }
+
+    fn next_index(&self, index: u32) -> u32 {
+        if index >= self.max_counter {
+            1
+        } else {
+            index + 1
+        }
+    }
+}
+
+/// A log file of a filter
+struct LogFile {
+    name: String,
+    index: u32,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Messages of a filter waiting to be written
+struct Cache {
+    messages: Vec<Vec<u8>>,
+    used: usize,
+    size: usize,
+}
+
+/// The files written for one filter
+struct FilterFiles {
+    filter: LogstorageFilter,
+    file: Option<File>,
+    written: u64,
+    index: u32,
+    /// `None` if every message is written right away
+    cache: Option<Cache>,
+}
+
+impl FilterFiles {
+    fn log_files(&self, directory: &Path, naming: &FileNaming) -> io::Result<Vec<LogFile>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(index) = naming.index(&self.filter.file_name, &name) {
+                let metadata = entry.metadata()?;
+                files.push(LogFile {
+                    name,
+                    index,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    /// Continue the newest existing file if it has room left, else start a new one
+    fn open(&mut self, directory: &Path, naming: &FileNaming) -> io::Result<()> {
+        let files = self.log_files(directory, naming)?;
+        match files.iter().max_by_key(|file| (file.modified, file.index)) {
+            Some(newest) if newest.size < self.filter.file_size as u64 => {
+                self.file = Some(
+                    fs::OpenOptions::new()
+                        .append(true)
+                        .open(directory.join(&newest.name))?,
+                );
+                self.written = newest.size;
+                self.index = newest.index;
+                Ok(())
+            }
+            Some(newest) => {
+                self.index = newest.index;
+                self.create(directory, naming)
+            }
+            None => {
+                self.index = 0;
+                self.create(directory, naming)
+            }
+        }
+    }
+
+    /// Start the next file, deleting the oldest ones to stay within NOFiles
+    fn create(&mut self, directory: &Path, naming: &FileNaming) -> io::Result<()> {
+        self.file = None;
+        let mut files = self.log_files(directory, naming)?;
+        // the oldest file follows the current index, as the index wraps around
+        let current = self.index;
+        files.sort_by_key(|file| (file.index <= current, file.index));
+        let excess = (files.len() + 1).saturating_sub(self.filter.num_files as usize);
+        for file in files.iter().take(excess) {
+            fs::remove_file(directory.join(&file.name))?;
+        }
+
+        self.index = naming.next_index(self.index);
+        let path = directory.join(naming.name(&self.filter.file_name, self.index));
+        self.file = Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)?,
+        );
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Write a message, or cache it according to the sync strategy. A full
+    /// cache is written before the message is added.
+    fn store(&mut self, directory: &Path, naming: &FileNaming, data: &[u8]) -> io::Result<()> {
+        let Some(cache) = &self.cache else {
+            return self.write(directory, naming, data);
+        };
+        if cache.used + data.len() > cache.size {
+            self.flush(directory, naming)?;
+        }
+        if let Some(cache) = &mut self.cache {
+            cache.used += data.len();
+            cache.messages.push(data.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Write the cached messages and sync the file to the device
+    fn flush(&mut self, directory: &Path, naming: &FileNaming) -> io::Result<()> {
+        let Some(cache) = &mut self.cache else {
+            return Ok(());
+        };
+        let messages = std::mem::take(&mut cache.messages);
+        cache.used = 0;
+        if messages.is_empty() {
+            return Ok(());
+        }
+        for data in messages {
+            self.write(directory, naming, &data)?;
+        }
+        if let Some(file) = &self.file {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, directory: &Path, naming: &FileNaming, data: &[u8]) -> io::Result<()> {
+        let len = data.len() as u64;
+        if self.file.is_none() {
+            self.open(directory, naming)?;
+        }
+        if self.written > 0 && self.written + len > self.filter.file_size as u64 {
+            self.create(directory, naming)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(data)?;
+            self.written += len;
+        }
+        Ok(())
+    }
+}
+
+/// A connected logstorage device, storing log messages according to the
+/// filters in its `dlt_logstorage.conf`
+pub(crate) struct LogstorageDevice {
+    mount_point: PathBuf,
+    naming: FileNaming,
+    ecu_id: String,
+    filters: Vec<FilterFiles>,
+}
+
+impl LogstorageDevice {
+    pub fn connect(mount_point: &Path, config: &DaemonConfig) -> Result<Self, DltError> {
+        let config_file = mount_point.join(DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME);
+        let logstorage_config = LogstorageConfig::from_file(&config_file.to_string_lossy())?;
+        for skipped in &logstorage_config.skipped {
+            log_warning!("{}", skipped);
+        }
+        if logstorage_config.filters.is_empty() {
+            log_warning!("No valid filter in {}", config_file.display());
+            return Err(DltError::ConfigFileError(
+                config_file.to_string_lossy().into_owned(),
+            ));
+        }
+        // the caches of all filters share the configured cache size
+        let mut cache_budget = config.offline_logstorage_cache_size as usize * 1024;
+        let filters = logstorage_config
+            .filters
+            .into_iter()
+            .map(|filter| {
+                let cache = cache_size(&filter).and_then(|size| {
+                    if size > cache_budget {
+                        log_warning!(
+                            "Logstorage: no cache left for {}, writing every message",
+                            filter.name
+                        );
+                        return None;
+                    }
+                    cache_budget -= size;
+                    Some(Cache {
+                        messages: Vec::new(),
+                        used: 0,
+                        size,
+                    })
+                });
+                FilterFiles {
+                    filter,
+                    file: None,
+                    written: 0,
+                    index: 0,
+                    cache,
+                }
+            })
+            .collect();
+        Ok(LogstorageDevice {
+            mount_point: mount_point.to_owned(),
+            naming: FileNaming::from_config(config),
+            ecu_id: config.ecu_id.clone(),
+            filters,
+        })
+    }
+
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// Store a log message in the files of all matching filters
+    pub fn write(&mut self, message: &DltMessage, header: &HeaderInfo) {
+        if header.message_type != DLT_TYPE_LOG {
+            return;
+        }
+        let ecu_id = header
+            .ecu_id
+            .map(|ecu_id| id_to_string(&ecu_id))
+            .unwrap_or_else(|| self.ecu_id.clone());
+        let app_id = id_to_string(&header.app_id);
+        let context_id = id_to_string(&header.context_id);
+
+        let mut data = Vec::new();
+        for filter in self.filters.iter_mut() {
+            if !filter
+                .filter
+                .matches(&ecu_id, &app_id, &context_id, header.message_type_info)
+            {
+                continue;
+            }
+            if data.is_empty() {
+                data.extend_from_slice(&storage_header(&ecu_id));
+                data.extend_from_slice(message.as_bytes());
+            }
+            let result = filter.store(&self.mount_point, &self.naming, &data);
+            report(&self.mount_point, filter, result);
+        }
+    }
+
+    /// Write the caches of the filters syncing on demand
+    pub fn sync_on_demand(&mut self) {
+        self.sync(DLT_LOGSTORAGE_SYNC_ON_DEMAND);
+    }
+
+    /// Write all caches, before the device is disconnected or the daemon exits
+    pub fn flush(&mut self) {
+        self.sync(u32::MAX);
+    }
+
+    fn sync(&mut self, sync_behavior: u32) {
+        for filter in self.filters.iter_mut() {
+            if filter.filter.sync_behavior & sync_behavior != 0 {
+                let result = filter.flush(&self.mount_point, &self.naming);
+                report(&self.mount_point, filter, result);
+            }
+        }
+    }
+}
+
+/// A failed write closes the file, the next message opens it again
+fn report(mount_point: &Path, filter: &mut FilterFiles, result: io::Result<()>) {
+    if let Err(e) = result {
+        log_error!(
+            "Logstorage: failed to write {} on {}: {}",
+            filter.filter.file_name,
+            mount_point.display(),
+            e
+        );
+        filter.file = None;
+    }
+}
+
+/// Size of the cache of a filter, `None` if messages are written right away
+fn cache_size(filter: &LogstorageFilter) -> Option<usize> {
+    if filter.sync_behavior & DLT_LOGSTORAGE_SYNC_ON_MSG != 0 {
+        None
+    } else if filter.sync_behavior & DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE != 0 {
+        Some(filter.specific_size as usize)
+    } else {
+        Some(filter.file_size as usize)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    /// A verbose log message with an extended header
+    pub(crate) fn log_message(
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+        log_level: u8,
+        payload_len: usize,
+    ) -> DltMessage {
+        let len = 4 + 4 + 10 + payload_len;
+        let mut data = vec![0x25, 0];
+        data.extend_from_slice(&(len as u16).to_be_bytes());
+        data.extend_from_slice(b"ECU1");
+        data.extend_from_slice(&[(log_level << 4) | 1, 0]);
+        data.extend_from_slice(app_id);
+        data.extend_from_slice(context_id);
+        data.resize(len, 0);
+        DltMessage::new(data)
+    }
+
+    fn test_device(name: &str, config: &str) -> TestDir {
+        let dir = TestDir::new(&format!("logstorage-{}", name));
+        fs::write(dir.join(DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME), config).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(DLT_OFFLINE_LOGSTORAGE_FILE_EXTENSION))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn write(device: &mut LogstorageDevice, message: &DltMessage) {
+        device.write(message, &message.header().unwrap());
+    }
+
+    #[test]
+    fn filter_and_rotate() {
+        let dir = test_device(
+            "rotate",
+            "[FILTER1]\nLogAppName=APP1\nContextName=.*\nLogLevel=DLT_LOG_WARN\n\
+             File=app1\nFileSize=100\nNOFiles=2\n\n\
+             [FILTER2]\nLogAppName=.*\nContextName=CON2\nLogLevel=DLT_LOG_VERBOSE\n\
+             File=con2\nFileSize=1000\nNOFiles=1\n",
+        );
+        let config = DaemonConfig {
+            offline_logstorage_timestamp: false,
+            offline_logstorage_max_counter: 999,
+            ..Default::default()
+        };
+        let mut device = LogstorageDevice::connect(&dir, &config).unwrap();
+
+        // 16 bytes storage header and 34 bytes message, two fit into one file
+        let warning = log_message(b"APP1", b"CON1", 3, 16);
+        for _ in 0..5 {
+            write(&mut device, &warning);
+        }
+        write(&mut device, &log_message(b"APP1", b"CON1", 4, 16));
+        write(&mut device, &log_message(b"APP2", b"CON2", 6, 16));
+
+        assert_eq!(
+            file_names(&dir),
+            vec!["app1_002.dlt", "app1_003.dlt", "con2_001.dlt"]
+        );
+        let data = fs::read(dir.join("app1_003.dlt")).unwrap();
+        assert_eq!(data.len(), 50);
+        assert_eq!(&data[..4], b"DLT\x01");
+        assert_eq!(&data[16..], warning.as_bytes());
+        assert_eq!(fs::read(dir.join("con2_001.dlt")).unwrap().len(), 50);
+
+        // reconnecting continues in the newest file
+        drop(device);
+        let mut device = LogstorageDevice::connect(&dir, &config).unwrap();
+        write(&mut device, &warning);
+        assert_eq!(fs::read(dir.join("app1_003.dlt")).unwrap().len(), 100);
+        write(&mut device, &warning);
+        assert_eq!(
+            file_names(&dir),
+            vec!["app1_003.dlt", "app1_004.dlt", "con2_001.dlt"]
+        );
+    }
+
+    #[test]
+    fn sync_strategies() {
+        let dir = test_device(
+            "sync",
+            "[FILTER1]\nLogAppName=APP1\nLogLevel=DLT_LOG_VERBOSE\nFile=demand\n\
+             FileSize=1000\nNOFiles=1\nSyncBehavior=ON_DEMAND\n\n\
+             [FILTER2]\nLogAppName=APP1\nLogLevel=DLT_LOG_VERBOSE\nFile=specific\n\
+             FileSize=1000\nNOFiles=1\nSyncBehavior=ON_SPECIFIC_SIZE\nSpecificSize=100\n\n\
+             [FILTER3]\nLogAppName=APP1\nLogLevel=DLT_LOG_VERBOSE\nFile=exit\n\
+             FileSize=1000\nNOFiles=1\nSyncBehavior=ON_DAEMON_EXIT\n",
+        );
+        let config = DaemonConfig {
+            offline_logstorage_timestamp: false,
+            offline_logstorage_max_counter: 9,
+            ..Default::default()
+        };
+        let size = |name: &str| fs::metadata(dir.join(name)).map(|m| m.len()).unwrap_or(0);
+        let mut device = LogstorageDevice::connect(&dir, &config).unwrap();
+
+        // 50 bytes with the storage header
+        let message = log_message(b"APP1", b"CON1", 4, 16);
+        for _ in 0..3 {
+            write(&mut device, &message);
+        }
+        assert_eq!(size("demand_1.dlt"), 0);
+        // the third message did not fit into the cache
+        assert_eq!(size("specific_1.dlt"), 100);
+        assert_eq!(size("exit_1.dlt"), 0);
+
+        device.sync_on_demand();
+        assert_eq!(size("demand_1.dlt"), 150);
+        assert_eq!(size("exit_1.dlt"), 0);
+
+        device.flush();
+        assert_eq!(size("specific_1.dlt"), 150);
+        assert_eq!(size("exit_1.dlt"), 150);
+    }
+
+    #[test]
+    fn cache_size_limit() {
+        let dir = test_device(
+            "cache",
+            "[FILTER1]\nLogAppName=APP1\nLogLevel=DLT_LOG_VERBOSE\nFile=big\n\
+             FileSize=2048\nNOFiles=1\nSyncBehavior=ON_DEMAND\n",
+        );
+        let config = DaemonConfig {
+            offline_logstorage_cache_size: 1,
+            ..Default::default()
+        };
+        let mut device = LogstorageDevice::connect(&dir, &config).unwrap();
+        assert!(device.filters[0].cache.is_none());
+        write(&mut device, &log_message(b"APP1", b"CON1", 4, 16));
+        assert_eq!(file_names(&dir).len(), 1);
+    }
+
+    #[test]
+    fn file_naming() {
+        let naming = FileNaming {
+            delimiter: "_".to_owned(),
+            timestamp: true,
+            max_counter: 99,
+        };
+        let name = naming.name("log", 7);
+        assert!(name.starts_with("log_07_"));
+        assert_eq!(naming.index("log", &name), Some(7));
+        assert_eq!(naming.index("other", &name), None);
+        assert_eq!(naming.next_index(99), 1);
+
+        let naming = FileNaming {
+            delimiter: "_".to_owned(),
+            timestamp: false,
+            max_counter: u32::MAX,
+        };
+        assert_eq!(naming.name("log", 7), "log_7.dlt");
+    }
+
+    #[test]
+    fn missing_configuration() {
+        let dir = TestDir::new("logstorage-missing");
+        assert!(LogstorageDevice::connect(&dir, &DaemonConfig::default()).is_err());
+    }
+}
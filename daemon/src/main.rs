@@ -1,3 +1,62 @@
+mod client;
+mod cmdline;
+mod control;
+mod control_socket;
+mod daemon;
+mod daemonize;
+mod fifo;
+mod gateway;
+mod logging;
+mod logstorage;
+mod message;
+mod offline_trace;
+mod registry;
+mod ring_buffer;
+mod serial;
+mod signals;
+#[cfg(test)]
+mod test_util;
+mod udp;
+mod user_socket;
+
+use cmdline::{usage, CommandLine};
+use logging::{init_logging, log_error};
+
 fn main() {
-    println!("Hello, world!");
+    let command_line = match CommandLine::parse(std::env::args().skip(1)) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            eprintln!("{e}");
+            usage();
+            std::process::exit(-1);
+        }
+    };
+    if command_line.help {
+        // like the C daemon, printing the usage ends with an error code
+        usage();
+        std::process::exit(-1);
+    }
+
+    let config = command_line.load_config();
+    init_logging(&config.logging_mode, config.logging_level);
+
+    // forking is only safe while the process has a single thread
+    if config.daemonize {
+        if let Err(e) = daemonize::daemonize() {
+            log_error!("Failed to daemonize: {e}");
+            std::process::exit(-1);
+        }
+    }
+    let signals = match signals::handle_signals() {
+        Ok(signals) => signals,
+        Err(e) => {
+            log_error!("Failed to set up signal handling: {e}");
+            std::process::exit(-1);
+        }
+    };
+
+    if let Err(e) = async_std::task::block_on(daemon::run(command_line, config, signals)) {
+        log_error!("DLT daemon stopped: {e}");
+        std::process::exit(-1);
+    }
 }
@@ -0,0 +1,301 @@
+use dlt_core::dlt::{
+    Argument, ControlType, Endianness, ExtendedHeaderConfig, LogLevel, Message, MessageConfig,
+    MessageType, PayloadContent, StringCoding, TypeInfo, TypeInfoKind, Value,
+};
+
+/// Pattern sent in front of every message when the serial header is enabled
+pub(crate) const DLT_SERIAL_HEADER: [u8; 4] = [b'D', b'L', b'S', 1];
+
+/// Pattern at the start of the storage header
+const DLT_STORAGE_HEADER_PATTERN: [u8; 4] = [b'D', b'L', b'T', 1];
+
+/// Application and context id the daemon uses for control messages
+const DLT_DAEMON_CTRL_APID: &str = "DA1";
+const DLT_DAEMON_CTRL_CTID: &str = "DC1";
+
+/// Application and context id of the status messages the daemon logs itself
+const DLT_DAEMON_INTERNAL_APID: &str = "DLTD";
+const DLT_DAEMON_INTERNAL_CTID: &str = "INTM";
+
+pub(crate) const DLT_SERVICE_ID_MESSAGE_BUFFER_OVERFLOW: u32 = 0x14;
+pub(crate) const DLT_SERVICE_ID_CONNECTION_INFO: u32 = 0xF02;
+
+pub(crate) const DLT_SERVICE_RESPONSE_OK: u8 = 0;
+
+pub(crate) const DLT_CONNECTION_STATUS_DISCONNECTED: u8 = 1;
+pub(crate) const DLT_CONNECTION_STATUS_CONNECTED: u8 = 2;
+
+// Flags of the standard header type
+const DLT_HTYP_UEH: u8 = 0x01;
+const DLT_HTYP_MSBF: u8 = 0x02;
+const DLT_HTYP_WEID: u8 = 0x04;
+const DLT_HTYP_WSID: u8 = 0x08;
+const DLT_HTYP_WTMS: u8 = 0x10;
+
+const DLT_EXTENDED_HEADER_SIZE: usize = 10;
+
+pub(crate) const DLT_TYPE_LOG: u8 = 0;
+pub(crate) const DLT_TYPE_CONTROL: u8 = 3;
+
+/// Fields of the standard and extended header
+#[derive(Debug, PartialEq)]
+pub(crate) struct HeaderInfo {
+    pub ecu_id: Option<[u8; 4]>,
+    pub app_id: [u8; 4],
+    pub context_id: [u8; 4],
+    /// Message type, e.g. log or control
+    pub message_type: u8,
+    /// Log level for log messages, request or response for control messages
+    pub message_type_info: u8,
+    pub big_endian: bool,
+    /// Offset of the payload in the message
+    pub payload_offset: usize,
+}
+
+/// Parse the headers of a message, `None` if it has no extended header
+pub(crate) fn parse_header(message: &[u8]) -> Option<HeaderInfo> {
+    let htyp = *message.first()?;
+    if htyp & DLT_HTYP_UEH == 0 {
+        return None;
+    }
+    let mut offset = 4;
+    let mut ecu_id = None;
+    if htyp & DLT_HTYP_WEID != 0 {
+        ecu_id = Some(message.get(offset..offset + 4)?.try_into().ok()?);
+        offset += 4;
+    }
+    for flag in [DLT_HTYP_WSID, DLT_HTYP_WTMS] {
+        if htyp & flag != 0 {
+            offset += 4;
+        }
+    }
+    let extended_header = message.get(offset..offset + DLT_EXTENDED_HEADER_SIZE)?;
+    let msin = extended_header[0];
+    Some(HeaderInfo {
+        ecu_id,
+        app_id: extended_header[2..6].try_into().ok()?,
+        context_id: extended_header[6..10].try_into().ok()?,
+        message_type: (msin >> 1) & 0x07,
+        message_type_info: msin >> 4,
+        big_endian: htyp & DLT_HTYP_MSBF != 0,
+        payload_offset: offset + DLT_EXTENDED_HEADER_SIZE,
+    })
+}
+
+/// Time since boot in units of 0.1 milliseconds, as used in the standard header
+pub(crate) fn uptime() -> u32 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    (time.tv_sec as u64 * 10000 + time.tv_nsec as u64 / 100000) as u32
+}
+
+/// Offset of the local time to UTC in seconds and whether daylight saving
+/// time is in effect
+pub(crate) fn local_timezone() -> (i32, bool) {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    (tm.tm_gmtoff as i32, tm.tm_isdst > 0)
+}
+
+/// Storage header put in front of messages written to files, with the
+/// current time and `ecu_id`
+pub(crate) fn storage_header(ecu_id: &str) -> [u8; 16] {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut time) };
+    let mut header = [0u8; 16];
+    header[..4].copy_from_slice(&DLT_STORAGE_HEADER_PATTERN);
+    header[4..8].copy_from_slice(&(time.tv_sec as u32).to_le_bytes());
+    header[8..12].copy_from_slice(&((time.tv_nsec / 1000) as i32).to_le_bytes());
+    for (i, byte) in ecu_id.bytes().take(4).enumerate() {
+        header[12 + i] = byte;
+    }
+    header
+}
+
+/// A DLT message in wire format, starting at the standard header
+#[derive(Clone, Debug)]
+pub(crate) struct DltMessage {
+    data: Vec<u8>,
+}
+
+impl DltMessage {
+    pub fn new(data: Vec<u8>) -> Self {
+        DltMessage { data }
+    }
+
+    /// Build a control response originating from the daemon.
+    /// `payload` holds the parameters following the service id.
+    pub fn control_response(ecu_id: &str, service_id: u32, payload: Vec<u8>) -> Self {
+        Self::control(ecu_id, ControlType::Response, service_id, payload)
+    }
+
+    /// Build a control request for the daemon of `ecu_id`
+    pub fn control_request(ecu_id: &str, service_id: u32, payload: Vec<u8>) -> Self {
+        Self::control(ecu_id, ControlType::Request, service_id, payload)
+    }
+
+    fn control(ecu_id: &str, control_type: ControlType, service_id: u32, payload: Vec<u8>) -> Self {
+        let config = MessageConfig {
+            version: 1,
+            counter: 0,
+            endianness: Endianness::Little,
+            ecu_id: Some(ecu_id.to_owned()),
+            session_id: None,
+            timestamp: Some(uptime()),
+            payload: PayloadContent::NonVerbose(service_id, payload),
+            extended_header_info: Some(ExtendedHeaderConfig {
+                message_type: MessageType::Control(control_type),
+                app_id: DLT_DAEMON_CTRL_APID.to_owned(),
+                context_id: DLT_DAEMON_CTRL_CTID.to_owned(),
+            }),
+        };
+        DltMessage::new(Message::new(config, None).as_bytes())
+    }
+
+    /// Connection info response announcing a changed client connection state
+    pub fn connection_info(ecu_id: &str, state: u8, communication_interface: &[u8; 4]) -> Self {
+        let mut payload = vec![DLT_SERVICE_RESPONSE_OK, state];
+        payload.extend_from_slice(communication_interface);
+        DltMessage::control_response(ecu_id, DLT_SERVICE_ID_CONNECTION_INFO, payload)
+    }
+
+    /// Response reporting the number of messages lost in the daemon buffer
+    pub fn buffer_overflow(ecu_id: &str, overflow_counter: u32) -> Self {
+        let mut payload = vec![DLT_SERVICE_RESPONSE_OK, 1];
+        payload.extend_from_slice(&overflow_counter.to_le_bytes());
+        DltMessage::control_response(ecu_id, DLT_SERVICE_ID_MESSAGE_BUFFER_OVERFLOW, payload)
+    }
+
+    /// Verbose info message with a single string argument, used by the
+    /// daemon to report its own status
+    pub fn internal_log(ecu_id: &str, counter: u8, text: &str) -> Self {
+        let argument = Argument {
+            type_info: TypeInfo {
+                kind: TypeInfoKind::StringType,
+                coding: StringCoding::UTF8,
+                has_variable_info: false,
+                has_trace_info: false,
+            },
+            name: None,
+            unit: None,
+            fixed_point: None,
+            value: Value::StringVal(text.to_owned()),
+        };
+        let config = MessageConfig {
+            version: 1,
+            counter,
+            endianness: Endianness::Little,
+            ecu_id: Some(ecu_id.to_owned()),
+            session_id: Some(std::process::id()),
+            timestamp: Some(uptime()),
+            payload: PayloadContent::Verbose(vec![argument]),
+            extended_header_info: Some(ExtendedHeaderConfig {
+                message_type: MessageType::Log(LogLevel::Info),
+                app_id: DLT_DAEMON_INTERNAL_APID.to_owned(),
+                context_id: DLT_DAEMON_INTERNAL_CTID.to_owned(),
+            }),
+        };
+        DltMessage::new(Message::new(config, None).as_bytes())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn header(&self) -> Option<HeaderInfo> {
+        parse_header(&self.data)
+    }
+}
+
+/// Splits a byte stream into DLT messages. Messages may be preceded by a
+/// serial header.
+#[derive(Default)]
+pub(crate) struct MessageStream {
+    buffer: Vec<u8>,
+}
+
+impl MessageStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete message, or `None` if more data is needed
+    pub fn next_message(&mut self) -> Option<Vec<u8>> {
+        if self.buffer.starts_with(&DLT_SERIAL_HEADER) {
+            self.buffer.drain(..DLT_SERIAL_HEADER.len());
+        }
+        if self.buffer.len() < 4 {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as usize;
+        if len < 4 {
+            // not a DLT message, the stream cannot be recovered
+            self.buffer.clear();
+            return None;
+        }
+        if self.buffer.len() < len {
+            return None;
+        }
+        Some(self.buffer.drain(..len).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlt_core::parse::{dlt_message, ParsedMessage};
+
+    #[test]
+    fn connection_info() {
+        let message = DltMessage::connection_info("ECU1", DLT_CONNECTION_STATUS_CONNECTED, b"remo");
+        let (rest, parsed) = dlt_message(message.as_bytes(), None, false).unwrap();
+        assert!(rest.is_empty());
+        let ParsedMessage::Item(parsed) = parsed else {
+            panic!("message not parsed");
+        };
+        assert_eq!(parsed.header.ecu_id, Some("ECU1".to_owned()));
+        let extended_header = parsed.extended_header.unwrap();
+        assert_eq!(
+            extended_header.message_type,
+            MessageType::Control(ControlType::Response)
+        );
+        // service id 0xF02 in little endian, status, state and interface
+        assert_eq!(
+            &message.as_bytes()[message.as_bytes().len() - 10..],
+            &[0x02, 0x0F, 0, 0, 0, 2, b'r', b'e', b'm', b'o']
+        );
+    }
+
+    #[test]
+    fn internal_log() {
+        let message = DltMessage::internal_log("ECU1", 7, "Daemon launched");
+        let (rest, parsed) = dlt_message(message.as_bytes(), None, false).unwrap();
+        assert!(rest.is_empty());
+        let ParsedMessage::Item(parsed) = parsed else {
+            panic!("message not parsed");
+        };
+        assert_eq!(parsed.header.message_counter, 7);
+        let extended_header = parsed.extended_header.unwrap();
+        assert!(extended_header.verbose);
+        assert_eq!(extended_header.application_id, "DLTD");
+        assert_eq!(extended_header.context_id, "INTM");
+        let PayloadContent::Verbose(arguments) = parsed.payload else {
+            panic!("not a verbose message");
+        };
+        assert_eq!(
+            arguments[0].value,
+            Value::StringVal("Daemon launched".to_owned())
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::message::{storage_header, DltMessage};
+use libdlt::config::{DaemonConfig, OfflineTraceFileName};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Trace files are named `dlt_offlinetrace.<index or timestamp>.dlt`
+const DLT_OFFLINETRACE_FILENAME_BASE: &str = "dlt_offlinetrace";
+const DLT_OFFLINETRACE_FILENAME_DELI: char = '.';
+const DLT_OFFLINETRACE_FILENAME_EXT: &str = ".dlt";
+
+/// Local time formatted as `YYYYmmdd-HHMMSS`, as used in file names
+pub(crate) fn file_timestamp() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// Writes all messages into files in a directory. A new file is started once
+/// a file reaches `file_size`, and the oldest files are deleted so all files
+/// together stay below `max_size`.
+pub(crate) struct OfflineTrace {
+    directory: PathBuf,
+    file_size: u64,
+    max_size: u64,
+    timestamp_based: bool,
+    ecu_id: String,
+    file: File,
+    written: u64,
+    /// Index of the current file, continues after the newest existing file
+    index: u32,
+}
+
+impl OfflineTrace {
+    pub fn new(directory: &Path, config: &DaemonConfig) -> io::Result<Self> {
+        let timestamp_based = matches!(
+            config.offline_trace_file_name,
+            OfflineTraceFileName::OfflineTraceFileNameTimeStampBased
+        );
+        let newest = trace_files(directory)?
+            .iter()
+            .filter_map(|(name, _)| file_index(name))
+            .max()
+            .unwrap_or(0);
+        let file_size = config.offline_trace_file_size as u64;
+        let max_size = config.offline_trace_max_size as u64;
+        let (file, written) =
+            create_file(directory, file_size, max_size, timestamp_based, newest + 1)?;
+        Ok(OfflineTrace {
+            directory: directory.to_owned(),
+            file_size,
+            max_size,
+            timestamp_based,
+            ecu_id: config.ecu_id.clone(),
+            file,
+            written,
+            index: newest + 1,
+        })
+    }
+
+    /// Store a message with a storage header
+    pub fn write(&mut self, message: &DltMessage) -> io::Result<()> {
+        let header = storage_header(&self.ecu_id);
+        let len = (header.len() + message.as_bytes().len()) as u64;
+        if self.written > 0 && self.written + len > self.file_size {
+            self.index = self.index.wrapping_add(1);
+            (self.file, self.written) = create_file(
+                &self.directory,
+                self.file_size,
+                self.max_size,
+                self.timestamp_based,
+                self.index,
+            )?;
+        }
+        let mut data = Vec::with_capacity(len as usize);
+        data.extend_from_slice(&header);
+        data.extend_from_slice(message.as_bytes());
+        self.file.write_all(&data)?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+fn file_name(timestamp_based: bool, index: u32) -> String {
+    let name = if timestamp_based {
+        file_timestamp()
+    } else {
+        format!("{:010}", index)
+    };
+    format!(
+        "{DLT_OFFLINETRACE_FILENAME_BASE}{DLT_OFFLINETRACE_FILENAME_DELI}{name}{DLT_OFFLINETRACE_FILENAME_EXT}"
+    )
+}
+
+/// Delete the oldest files to make room for a new one and open it.
+/// Returns the file and its current size.
+fn create_file(
+    directory: &Path,
+    file_size: u64,
+    max_size: u64,
+    timestamp_based: bool,
+    index: u32,
+) -> io::Result<(File, u64)> {
+    let mut files = trace_files(directory)?;
+    // zero padded indexes and timestamps sort by age
+    files.sort();
+    let mut total: u64 = files.iter().map(|(_, size)| size).sum();
+    let limit = max_size.saturating_sub(file_size);
+    for (name, size) in files {
+        if total <= limit {
+            break;
+        }
+        fs::remove_file(directory.join(name))?;
+        total -= size;
+    }
+
+    // files named by timestamp may already exist when rotating quickly
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(directory.join(file_name(timestamp_based, index)))?;
+    let written = file.metadata()?.len();
+    Ok((file, written))
+}
+
+/// Names and sizes of the trace files in `directory`
+fn trace_files(directory: &Path) -> io::Result<Vec<(String, u64)>> {
+    let prefix = format!("{DLT_OFFLINETRACE_FILENAME_BASE}{DLT_OFFLINETRACE_FILENAME_DELI}");
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(DLT_OFFLINETRACE_FILENAME_EXT) {
+            files.push((name, entry.metadata()?.len()));
+        }
+    }
+    Ok(files)
+}
+
+/// Index of an index based file name
+fn file_index(name: &str) -> Option<u32> {
+    name.strip_prefix(DLT_OFFLINETRACE_FILENAME_BASE)?
+        .strip_prefix(DLT_OFFLINETRACE_FILENAME_DELI)?
+        .strip_suffix(DLT_OFFLINETRACE_FILENAME_EXT)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = trace_files(dir)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotate_and_restore_index() {
+        let dir = TestDir::new("offline-trace-index");
+        let config = DaemonConfig {
+            offline_trace_file_size: 100,
+            offline_trace_max_size: 300,
+            offline_trace_file_name: OfflineTraceFileName::OfflineTraceFileNameIndexBased,
+            ..Default::default()
+        };
+        // 16 bytes storage header and 34 bytes message
+        let message = DltMessage::new(vec![0x20, 0, 0, 34].into_iter().chain([0; 30]).collect());
+
+        let mut offline_trace = OfflineTrace::new(&dir, &config).unwrap();
+        for _ in 0..8 {
+            offline_trace.write(&message).unwrap();
+        }
+        // two messages per file, the oldest file is deleted to stay below the maximum
+        assert_eq!(
+            file_names(&dir),
+            vec![
+                "dlt_offlinetrace.0000000002.dlt",
+                "dlt_offlinetrace.0000000003.dlt",
+                "dlt_offlinetrace.0000000004.dlt"
+            ]
+        );
+        let data = fs::read(dir.join("dlt_offlinetrace.0000000004.dlt")).unwrap();
+        assert_eq!(data.len(), 100);
+        assert_eq!(&data[..4], b"DLT\x01");
+        assert_eq!(&data[12..16], b"ECU1");
+        assert_eq!(&data[16..50], message.as_bytes());
+
+        // a restart continues after the newest file
+        drop(offline_trace);
+        OfflineTrace::new(&dir, &config).unwrap();
+        assert_eq!(
+            file_names(&dir).last().unwrap(),
+            "dlt_offlinetrace.0000000005.dlt"
+        );
+    }
+
+    #[test]
+    fn timestamp_based_name() {
+        let dir = TestDir::new("offline-trace-timestamp");
+        OfflineTrace::new(&dir, &DaemonConfig::default()).unwrap();
+        let names = file_names(&dir);
+        assert_eq!(names.len(), 1);
+        // dlt_offlinetrace.YYYYmmdd-HHMMSS.dlt
+        assert_eq!(names[0].len(), 36);
+        assert_eq!(&names[0][25..26], "-");
+    }
+}
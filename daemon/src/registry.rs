@@ -0,0 +1,782 @@
+use crate::daemon::id_to_string;
+use crate::logging::log_warning;
+use crate::user_socket::ConnectionId;
+use dlt_user::user_header::user_control_message::RegisterContext;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Files the runtime configuration is persisted in, same names as the C daemon
+const DLT_RUNTIME_APPLICATION_CFG: &str = "dlt-runtime-application.cfg";
+const DLT_RUNTIME_CONTEXT_CFG: &str = "dlt-runtime-context.cfg";
+
+pub(crate) type ContextKey = ([u8; 4], [u8; 4]);
+
+#[derive(Debug)]
+pub(crate) struct Application {
+    pub app_id: [u8; 4],
+    /// 0 if the application was loaded from the runtime configuration and has
+    /// not registered since
+    pub pid: u32,
+    pub description: String,
+    /// The connection the application registered on, not set for the FIFO.
+    /// The PID of such an application may be one of another PID namespace.
+    pub connection: Option<ConnectionId>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Context {
+    pub app_id: [u8; 4],
+    pub context_id: [u8; 4],
+    pub pid: u32,
+    pub connection: Option<ConnectionId>,
+    pub description: String,
+    pub log_level: i8,
+    pub trace_status: i8,
+    /// Position of the context in the management structure of the application
+    pub log_level_pos: i32,
+    /// Registered without a log level and not preconfigured, the log level
+    /// changes with the default log level
+    pub follows_default_log_level: bool,
+    /// Registered without a trace status, the trace status changes with the
+    /// default trace status
+    pub follows_default_trace_status: bool,
+}
+
+/// Log level for the contexts matching an entry of the preconfiguration file.
+/// Lines are `APID:CTID:LL` with a log level from 0 (off) to 6 (verbose), an
+/// empty id or `*` matches all ids.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PreconfiguredLogLevel {
+    app_id: [u8; 4],
+    context_id: [u8; 4],
+    log_level: i8,
+}
+
+impl PreconfiguredLogLevel {
+    fn parse(line: &str) -> Option<Self> {
+        let [app_id, context_id, log_level] = line.split(':').collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        let log_level = log_level
+            .trim()
+            .parse()
+            .ok()
+            .filter(|log_level| (0..=6).contains(log_level))?;
+        let id = |id: &str| match id.trim() {
+            "*" => Some([0; 4]),
+            id if id.len() <= 4 => Some(string_to_id(id)),
+            _ => None,
+        };
+        Some(PreconfiguredLogLevel {
+            app_id: id(app_id)?,
+            context_id: id(context_id)?,
+            log_level,
+        })
+    }
+
+    /// Number of ids given without wildcard, the most specific entry wins
+    fn specificity(&self) -> usize {
+        [self.app_id, self.context_id]
+            .iter()
+            .filter(|id| **id != [0; 4] && !id.contains(&b'*'))
+            .count()
+    }
+}
+
+/// Read the context log level preconfiguration. Empty lines and comments are
+/// ignored, malformed lines are reported and skipped.
+pub(crate) fn load_log_level_preconfig(path: &Path) -> io::Result<Vec<PreconfiguredLogLevel>> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match PreconfiguredLogLevel::parse(line) {
+            Some(entry) => entries.push(entry),
+            None => log_warning!("Invalid log level preconfiguration '{}' ignored", line),
+        }
+    }
+    Ok(entries)
+}
+
+/// Registered applications and contexts
+pub(crate) struct Registry {
+    applications: BTreeMap<[u8; 4], Application>,
+    contexts: BTreeMap<ContextKey, Context>,
+    default_log_level: i8,
+    default_trace_status: i8,
+    /// Highest log level and trace status of all contexts, if enforced
+    limit: Option<(i8, i8)>,
+    /// Used instead of the default log level for matching contexts
+    preconfig: Vec<PreconfiguredLogLevel>,
+    dirty: bool,
+}
+
+impl Registry {
+    pub fn new(default_log_level: i8, default_trace_status: i8) -> Self {
+        Registry {
+            applications: BTreeMap::new(),
+            contexts: BTreeMap::new(),
+            default_log_level,
+            default_trace_status,
+            limit: None,
+            preconfig: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    /// Change the default levels, also of the contexts following them.
+    /// Returns the contexts that changed.
+    pub fn set_defaults(
+        &mut self,
+        default_log_level: i8,
+        default_trace_status: i8,
+    ) -> Vec<&Context> {
+        self.default_log_level = default_log_level;
+        self.default_trace_status = default_trace_status;
+        let limit = self.limit;
+        self.update_contexts(&[0; 4], &[0; 4], |context| {
+            let mut log_level = context.log_level;
+            let mut trace_status = context.trace_status;
+            if context.follows_default_log_level {
+                log_level = default_log_level;
+            }
+            if context.follows_default_trace_status {
+                trace_status = default_trace_status;
+            }
+            if let Some((max_log_level, max_trace_status)) = limit {
+                log_level = log_level.min(max_log_level);
+                trace_status = trace_status.min(max_trace_status);
+            }
+            if (log_level, trace_status) == (context.log_level, context.trace_status) {
+                return false;
+            }
+            context.log_level = log_level;
+            context.trace_status = trace_status;
+            true
+        })
+    }
+
+    /// Replace the preconfigured log levels used for contexts registered from now on
+    pub fn set_preconfig(&mut self, preconfig: Vec<PreconfiguredLogLevel>) {
+        self.preconfig = preconfig;
+    }
+
+    /// Log level of a context registering with the default log level, if
+    /// an entry of the preconfiguration matches
+    fn preconfigured_log_level(&self, app_id: &[u8; 4], context_id: &[u8; 4]) -> Option<i8> {
+        let mut best: Option<&PreconfiguredLogLevel> = None;
+        for entry in &self.preconfig {
+            if id_matches(&entry.app_id, app_id)
+                && id_matches(&entry.context_id, context_id)
+                && best.is_none_or(|best| entry.specificity() > best.specificity())
+            {
+                best = Some(entry);
+            }
+        }
+        best.map(|entry| entry.log_level)
+    }
+
+    pub fn default_log_level(&self) -> i8 {
+        self.default_log_level
+    }
+
+    pub fn default_trace_status(&self) -> i8 {
+        self.default_trace_status
+    }
+
+    pub fn register_application(
+        &mut self,
+        app_id: [u8; 4],
+        pid: u32,
+        connection: Option<ConnectionId>,
+        description: String,
+    ) -> &Application {
+        self.dirty = true;
+        let application = self
+            .applications
+            .entry(app_id)
+            .or_insert_with(|| Application {
+                app_id,
+                pid,
+                description: String::new(),
+                connection,
+            });
+        application.pid = pid;
+        application.connection = connection;
+        application.description = description;
+        for context in self.contexts.values_mut() {
+            if context.app_id == app_id {
+                context.pid = pid;
+                context.connection = connection;
+            }
+        }
+        application
+    }
+
+    /// Remove an application and all of its contexts
+    pub fn unregister_application(&mut self, app_id: &[u8; 4]) -> Option<Application> {
+        let application = self.applications.remove(app_id)?;
+        self.contexts
+            .retain(|(context_app_id, _), _| context_app_id != app_id);
+        self.dirty = true;
+        Some(application)
+    }
+
+    /// Add a context. A context that is already known keeps its log level and
+    /// trace status, a new one gets the requested values, the preconfigured
+    /// log level or the defaults.
+    pub fn register_context(
+        &mut self,
+        register: &RegisterContext,
+        connection: Option<ConnectionId>,
+        description: String,
+    ) -> &Context {
+        self.dirty = true;
+        let (log_level, follows_default_log_level) = if register.log_level < 0 {
+            match self.preconfigured_log_level(&register.app_id, &register.context_id) {
+                Some(log_level) => (log_level, false),
+                None => (self.default_log_level, true),
+            }
+        } else {
+            (register.log_level, false)
+        };
+        let (trace_status, follows_default_trace_status) = if register.trace_status < 0 {
+            (self.default_trace_status, true)
+        } else {
+            (register.trace_status, false)
+        };
+        let context = self
+            .contexts
+            .entry((register.app_id, register.context_id))
+            .or_insert_with(|| Context {
+                app_id: register.app_id,
+                context_id: register.context_id,
+                pid: register.pid,
+                connection,
+                description: String::new(),
+                log_level,
+                trace_status,
+                log_level_pos: register.log_level_pos,
+                follows_default_log_level,
+                follows_default_trace_status,
+            });
+        context.pid = register.pid;
+        context.connection = connection;
+        context.log_level_pos = register.log_level_pos;
+        context.description = description;
+        if let Some((max_log_level, max_trace_status)) = self.limit {
+            context.log_level = context.log_level.min(max_log_level);
+            context.trace_status = context.trace_status.min(max_trace_status);
+        }
+        context
+    }
+
+    pub fn unregister_context(
+        &mut self,
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+    ) -> Option<Context> {
+        self.dirty = true;
+        self.contexts.remove(&(*app_id, *context_id))
+    }
+
+    pub fn application(&self, app_id: &[u8; 4]) -> Option<&Application> {
+        self.applications.get(app_id)
+    }
+
+    pub fn applications(&self) -> impl Iterator<Item = &Application> {
+        self.applications.values()
+    }
+
+    pub fn context(&self, app_id: &[u8; 4], context_id: &[u8; 4]) -> Option<&Context> {
+        self.contexts.get(&(*app_id, *context_id))
+    }
+
+    /// Description of a registered application, empty if it is not known
+    pub fn application_description(&self, app_id: &[u8; 4]) -> &str {
+        self.applications
+            .get(app_id)
+            .map(|application| application.description.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Contexts matching `app_id` and `context_id`, sorted by application.
+    /// An id of zeros matches all ids, an id ending with `*` matches by prefix.
+    pub fn matching_contexts(&self, app_id: &[u8; 4], context_id: &[u8; 4]) -> Vec<&Context> {
+        self.contexts
+            .values()
+            .filter(|context| {
+                id_matches(app_id, &context.app_id) && id_matches(context_id, &context.context_id)
+            })
+            .collect()
+    }
+
+    /// Enforce a highest log level and trace status on all contexts, or stop
+    /// enforcing it. Returns the contexts that were lowered.
+    pub fn set_limit(&mut self, limit: Option<(i8, i8)>) -> Vec<&Context> {
+        self.limit = limit;
+        let Some((max_log_level, max_trace_status)) = limit else {
+            return Vec::new();
+        };
+        self.update_contexts(&[0; 4], &[0; 4], |context| {
+            if context.log_level <= max_log_level && context.trace_status <= max_trace_status {
+                return false;
+            }
+            context.log_level = context.log_level.min(max_log_level);
+            context.trace_status = context.trace_status.min(max_trace_status);
+            true
+        })
+    }
+
+    /// Change the log level of the matching contexts, returns the changed contexts
+    pub fn set_log_level(
+        &mut self,
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+        log_level: i8,
+    ) -> Vec<&Context> {
+        self.update_contexts(app_id, context_id, |context| {
+            context.log_level = log_level;
+            context.follows_default_log_level = false;
+            true
+        })
+    }
+
+    /// Change the trace status of the matching contexts, returns the changed contexts
+    pub fn set_trace_status(
+        &mut self,
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+        trace_status: i8,
+    ) -> Vec<&Context> {
+        self.update_contexts(app_id, context_id, |context| {
+            context.trace_status = trace_status;
+            context.follows_default_trace_status = false;
+            true
+        })
+    }
+
+    /// Apply `update` to the matching contexts, it returns false if it did not
+    /// change the context
+    fn update_contexts(
+        &mut self,
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+        update: impl Fn(&mut Context) -> bool,
+    ) -> Vec<&Context> {
+        let mut changed = Vec::new();
+        for context in self.contexts.values_mut() {
+            if id_matches(app_id, &context.app_id)
+                && id_matches(context_id, &context.context_id)
+                && update(context)
+            {
+                changed.push(&*context);
+            }
+        }
+        if !changed.is_empty() {
+            self.dirty = true;
+        }
+        changed
+    }
+
+    /// Remove the applications whose process does not exist any more.
+    /// Applications registered on a connection are removed when it closes.
+    /// Returns the ids of the removed applications.
+    pub fn remove_exited_applications(&mut self) -> Vec<[u8; 4]> {
+        let exited: Vec<[u8; 4]> = self
+            .applications
+            .values()
+            .filter(|application| {
+                application.connection.is_none()
+                    && application.pid != 0
+                    && !process_exists(application.pid)
+            })
+            .map(|application| application.app_id)
+            .collect();
+        for app_id in &exited {
+            self.unregister_application(app_id);
+        }
+        exited
+    }
+
+    /// Remove the applications and contexts registered on a closed
+    /// connection. Returns the ids of the removed applications.
+    pub fn remove_connection(&mut self, connection: ConnectionId) -> Vec<[u8; 4]> {
+        let closed: Vec<[u8; 4]> = self
+            .applications
+            .values()
+            .filter(|application| application.connection == Some(connection))
+            .map(|application| application.app_id)
+            .collect();
+        for app_id in &closed {
+            self.unregister_application(app_id);
+        }
+        let contexts = self.contexts.len();
+        self.contexts
+            .retain(|_, context| context.connection != Some(connection));
+        if self.contexts.len() != contexts {
+            self.dirty = true;
+        }
+        closed
+    }
+
+    /// Returns true once after the registry has been changed
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Store the runtime configuration in `directory`
+    pub fn save(&self, directory: &Path) -> io::Result<()> {
+        let mut applications = String::new();
+        for application in self.applications.values() {
+            let _ = writeln!(
+                applications,
+                "{}:{}:",
+                id_to_string(&application.app_id),
+                config_field(&application.description)
+            );
+        }
+        write_config_file(&directory.join(DLT_RUNTIME_APPLICATION_CFG), &applications)?;
+
+        let mut contexts = String::new();
+        for context in self.contexts.values() {
+            let _ = writeln!(
+                contexts,
+                "{}:{}:{}:{}:{}:",
+                id_to_string(&context.app_id),
+                id_to_string(&context.context_id),
+                context.log_level,
+                context.trace_status,
+                config_field(&context.description)
+            );
+        }
+        write_config_file(&directory.join(DLT_RUNTIME_CONTEXT_CFG), &contexts)
+    }
+
+    /// Load a runtime configuration stored by `save`. Missing files are not an
+    /// error, malformed lines are skipped.
+    pub fn load(&mut self, directory: &Path) -> io::Result<()> {
+        for fields in read_config_lines(&directory.join(DLT_RUNTIME_APPLICATION_CFG))? {
+            if let [app_id, description, ..] = fields.as_slice() {
+                let app_id = string_to_id(app_id);
+                self.applications.insert(
+                    app_id,
+                    Application {
+                        app_id,
+                        pid: 0,
+                        description: description.clone(),
+                        connection: None,
+                    },
+                );
+            }
+        }
+
+        for fields in read_config_lines(&directory.join(DLT_RUNTIME_CONTEXT_CFG))? {
+            if let [app_id, context_id, log_level, trace_status, description, ..] =
+                fields.as_slice()
+            {
+                let (Ok(log_level), Ok(trace_status)) = (log_level.parse(), trace_status.parse())
+                else {
+                    continue;
+                };
+                let key = (string_to_id(app_id), string_to_id(context_id));
+                self.contexts.insert(
+                    key,
+                    Context {
+                        app_id: key.0,
+                        context_id: key.1,
+                        pid: 0,
+                        connection: None,
+                        description: description.clone(),
+                        log_level,
+                        trace_status,
+                        log_level_pos: 0,
+                        follows_default_log_level: false,
+                        follows_default_trace_status: false,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert a string to a zero padded 4 byte DLT id
+pub(crate) fn string_to_id(id: &str) -> [u8; 4] {
+    let mut result = [0u8; 4];
+    for (i, byte) in id.bytes().take(4).enumerate() {
+        result[i] = byte;
+    }
+    result
+}
+
+fn id_matches(pattern: &[u8; 4], id: &[u8; 4]) -> bool {
+    if *pattern == [0; 4] {
+        return true;
+    }
+    match pattern.iter().position(|byte| *byte == b'*') {
+        Some(wildcard) => pattern[..wildcard] == id[..wildcard],
+        None => pattern == id,
+    }
+}
+
+/// A description as a field of the runtime configuration, which separates
+/// the fields with colons and the entries with line breaks
+fn config_field(description: &str) -> String {
+    description.replace([':', '\n', '\r'], " ")
+}
+
+/// Replace the file at `path` with `contents`. A crash while writing leaves
+/// the previous file in place.
+fn write_config_file(path: &Path, contents: &str) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+fn read_config_lines(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.is_empty() {
+            lines.push(line.split(':').map(str::to_owned).collect());
+        }
+    }
+    Ok(lines)
+}
+
+fn process_exists(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestDir;
+    use std::fs;
+
+    /// Log level / trace status values used by the user library for "not set"
+    const DLT_LOG_DEFAULT: i8 = -1;
+    const DLT_USER_LOG_LEVEL_NOT_SET: i8 = -2;
+
+    fn register_context(
+        registry: &mut Registry,
+        app_id: &[u8; 4],
+        context_id: &[u8; 4],
+        pid: u32,
+        log_level: i8,
+        trace_status: i8,
+        description: &str,
+    ) {
+        let register = RegisterContext {
+            app_id: *app_id,
+            context_id: *context_id,
+            log_level_pos: 0,
+            log_level,
+            trace_status,
+            pid,
+            description_length: description.len() as u32,
+        };
+        registry.register_context(&register, None, description.to_owned());
+    }
+
+    fn registry_with_context() -> Registry {
+        let mut registry = Registry::new(4, 0);
+        registry.register_application(*b"APP1", std::process::id(), None, "Test app".to_owned());
+        register_context(
+            &mut registry,
+            b"APP1",
+            b"CTX1",
+            std::process::id(),
+            DLT_USER_LOG_LEVEL_NOT_SET,
+            DLT_LOG_DEFAULT,
+            "Test context",
+        );
+        register_context(
+            &mut registry,
+            b"APP1",
+            b"CTX2",
+            std::process::id(),
+            6,
+            1,
+            "Verbose context",
+        );
+        registry
+    }
+
+    #[test]
+    fn register_and_unregister() {
+        let mut registry = registry_with_context();
+        let context = &registry.contexts[&(*b"APP1", *b"CTX1")];
+        assert_eq!(context.log_level, 4);
+        assert_eq!(context.trace_status, 0);
+        assert_eq!(registry.contexts[&(*b"APP1", *b"CTX2")].log_level, 6);
+        assert_eq!(registry.contexts.len(), 2);
+
+        assert!(registry.unregister_context(b"APP1", b"CTX2").is_some());
+        assert_eq!(registry.contexts.len(), 1);
+
+        assert!(registry.unregister_application(b"APP1").is_some());
+        assert!(!registry.applications.contains_key(b"APP1"));
+        assert_eq!(registry.contexts.len(), 0);
+    }
+
+    #[test]
+    fn set_log_level_with_wildcards() {
+        let mut registry = registry_with_context();
+        register_context(&mut registry, b"APP2", b"CTX1", 1, 4, 0, "");
+
+        let changed = registry.set_log_level(b"APP1", b"CTX2", 2);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].context_id, *b"CTX2");
+
+        // all contexts of one application
+        assert_eq!(registry.set_log_level(b"APP1", &[0; 4], 3).len(), 2);
+        assert_eq!(registry.contexts[&(*b"APP2", *b"CTX1")].log_level, 4);
+
+        assert_eq!(registry.set_trace_status(b"APP*", b"CTX1", 1).len(), 2);
+        assert_eq!(registry.matching_contexts(b"APP*", b"CT*\0").len(), 3);
+        assert!(registry.set_log_level(b"APP3", &[0; 4], 1).is_empty());
+    }
+
+    #[test]
+    fn follow_default_log_level() {
+        let mut registry = registry_with_context();
+        register_context(&mut registry, b"APP1", b"CTX3", 1, DLT_LOG_DEFAULT, 1, "");
+        registry.set_log_level(b"APP1", b"CTX3", 2);
+
+        // only CTX1 registered with the default log level
+        let changed = registry.set_defaults(5, 0);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].context_id, *b"CTX1");
+        assert_eq!(changed[0].log_level, 5);
+        assert_eq!(registry.contexts[&(*b"APP1", *b"CTX3")].log_level, 2);
+
+        assert_eq!(registry.set_defaults(5, 1)[0].trace_status, 1);
+        assert!(registry.set_defaults(5, 1).is_empty());
+    }
+
+    #[test]
+    fn remove_exited_application() {
+        let mut registry = registry_with_context();
+        // pid of a process that has certainly exited
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        registry.register_application(*b"APP2", pid, None, "Exited app".to_owned());
+        register_context(&mut registry, b"APP2", b"CTX1", pid, 4, 0, "");
+        // the PID of another namespace
+        registry.register_application(*b"APP3", pid, Some(1), "Connected app".to_owned());
+
+        assert_eq!(registry.remove_exited_applications(), vec![*b"APP2"]);
+        assert!(registry.applications.contains_key(b"APP1"));
+        assert!(!registry.contexts.contains_key(&(*b"APP2", *b"CTX1")));
+
+        assert_eq!(registry.remove_connection(1), vec![*b"APP3"]);
+        assert!(registry.application(b"APP3").is_none());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TestDir::new("registry");
+
+        let mut registry = registry_with_context();
+        registry
+            .contexts
+            .get_mut(&(*b"APP1", *b"CTX1"))
+            .unwrap()
+            .log_level = 2;
+        registry.save(&dir).unwrap();
+
+        let mut loaded = Registry::new(4, 0);
+        loaded.load(&dir).unwrap();
+        assert_eq!(loaded.applications[b"APP1"].description, "Test app");
+        assert_eq!(loaded.applications[b"APP1"].pid, 0);
+        let context = &loaded.contexts[&(*b"APP1", *b"CTX1")];
+        assert_eq!(context.log_level, 2);
+        assert_eq!(context.description, "Test context");
+
+        // a registering context keeps the stored log level
+        register_context(&mut loaded, b"APP1", b"CTX1", 1, 5, 0, "Test context");
+        assert_eq!(loaded.contexts[&(*b"APP1", *b"CTX1")].log_level, 2);
+
+        // separators in a description do not break the stored entries
+        loaded
+            .contexts
+            .get_mut(&(*b"APP1", *b"CTX2"))
+            .unwrap()
+            .description = "multi:line\ncontext".to_owned();
+        loaded.save(&dir).unwrap();
+        let mut reloaded = Registry::new(4, 0);
+        reloaded.load(&dir).unwrap();
+        let context = &reloaded.contexts[&(*b"APP1", *b"CTX2")];
+        assert_eq!(context.description, "multi line context");
+        assert_eq!(context.log_level, 6);
+        assert_eq!(reloaded.contexts.len(), 2);
+        assert!(!dir.join("dlt-runtime-context.cfg.tmp").exists());
+    }
+
+    #[test]
+    fn preconfigured_log_level() {
+        let path = std::env::temp_dir().join(format!("dlt-daemon-ll-{}.conf", std::process::id()));
+        fs::write(
+            &path,
+            "# APID:CTID:LL\nAPP1:*:2\nAPP1:CTX2:5\n:CTX3:6\nAPP1:CTX1\nAPP1:CTX1:9\nAPP2:*:-1\n",
+        )
+        .unwrap();
+        let preconfig = load_log_level_preconfig(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(preconfig.len(), 3);
+        assert_eq!(
+            preconfig[0],
+            PreconfiguredLogLevel {
+                app_id: *b"APP1",
+                context_id: [0; 4],
+                log_level: 2
+            }
+        );
+
+        let mut registry = Registry::new(4, 0);
+        registry.set_preconfig(preconfig);
+        for context_id in [b"CTX1", b"CTX2", b"CTX3"] {
+            register_context(
+                &mut registry,
+                b"APP1",
+                context_id,
+                1,
+                DLT_USER_LOG_LEVEL_NOT_SET,
+                0,
+                "",
+            );
+        }
+        register_context(&mut registry, b"APP2", b"CTX1", 1, DLT_LOG_DEFAULT, 0, "");
+        register_context(&mut registry, b"APP2", b"CTX3", 1, DLT_LOG_DEFAULT, 0, "");
+        // a requested log level is kept
+        register_context(&mut registry, b"APP3", b"CTX3", 1, 1, 0, "");
+
+        let log_level =
+            |app_id, context_id| registry.context(app_id, context_id).unwrap().log_level;
+        assert_eq!(log_level(b"APP1", b"CTX1"), 2);
+        // the entry matching both ids wins
+        assert_eq!(log_level(b"APP1", b"CTX2"), 5);
+        assert_eq!(log_level(b"APP1", b"CTX3"), 2);
+        assert_eq!(log_level(b"APP2", b"CTX1"), 4);
+        assert_eq!(log_level(b"APP2", b"CTX3"), 6);
+        assert_eq!(log_level(b"APP3", b"CTX3"), 1);
+    }
+}
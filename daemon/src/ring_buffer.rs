@@ -0,0 +1,98 @@
+use crate::message::DltMessage;
+use std::collections::VecDeque;
+
+/// Stores messages while no client is connected. The buffer starts with
+/// `min_size` bytes and grows in steps of `step_size` up to `max_size`. Once
+/// the maximum is reached new messages are dropped and counted.
+pub(crate) struct RingBuffer {
+    messages: VecDeque<DltMessage>,
+    used: usize,
+    size: usize,
+    min_size: usize,
+    max_size: usize,
+    step_size: usize,
+    overflow_counter: u32,
+}
+
+impl RingBuffer {
+    pub fn new(min_size: u32, max_size: u32, step_size: u32) -> Self {
+        let min_size = min_size as usize;
+        RingBuffer {
+            messages: VecDeque::new(),
+            used: 0,
+            size: min_size,
+            min_size,
+            max_size: (max_size as usize).max(min_size),
+            step_size: step_size as usize,
+            overflow_counter: 0,
+        }
+    }
+
+    /// Change the size limits, stored messages are kept
+    pub fn set_limits(&mut self, min_size: u32, max_size: u32, step_size: u32) {
+        self.min_size = min_size as usize;
+        self.max_size = (max_size as usize).max(self.min_size);
+        self.step_size = step_size as usize;
+        self.size = self.size.clamp(self.min_size, self.max_size);
+    }
+
+    /// Store a message, returns false if it was dropped because the buffer is full
+    pub fn push(&mut self, message: DltMessage) -> bool {
+        let len = message.as_bytes().len();
+        while self.used + len > self.size {
+            if self.step_size == 0 || self.size + self.step_size > self.max_size {
+                self.overflow_counter = self.overflow_counter.wrapping_add(1);
+                return false;
+            }
+            self.size += self.step_size;
+        }
+        self.used += len;
+        self.messages.push_back(message);
+        true
+    }
+
+    /// Remove all messages in the order they were stored, and shrink the
+    /// buffer back to its minimum size
+    pub fn drain(&mut self) -> impl Iterator<Item = DltMessage> + '_ {
+        self.used = 0;
+        self.size = self.min_size;
+        self.messages.drain(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Number of messages dropped since the last call
+    pub fn take_overflow_counter(&mut self) -> u32 {
+        std::mem::take(&mut self.overflow_counter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(counter: u8) -> DltMessage {
+        DltMessage::new(vec![0x20, counter, 0x00, 0x0A, 0, 0, 0, 0, 0, 0])
+    }
+
+    #[test]
+    fn grows_and_overflows() {
+        let mut buffer = RingBuffer::new(20, 40, 10);
+        for counter in 0..4 {
+            assert!(buffer.push(message(counter)));
+        }
+        assert_eq!(buffer.size, 40);
+        assert!(!buffer.push(message(4)));
+        assert!(!buffer.push(message(5)));
+        assert_eq!(buffer.take_overflow_counter(), 2);
+        assert_eq!(buffer.take_overflow_counter(), 0);
+
+        let counters: Vec<u8> = buffer.drain().map(|m| m.as_bytes()[1]).collect();
+        assert_eq!(counters, vec![0, 1, 2, 3]);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.size, 20);
+        assert!(buffer.push(message(6)));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::client::handle_client;
+use crate::daemon::SharedDaemon;
+use crate::logging::{log_error, log_warning};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+fn baud_rate(baudrate: u32) -> Option<libc::speed_t> {
+    let speed = match baudrate {
+        50 => libc::B50,
+        75 => libc::B75,
+        110 => libc::B110,
+        134 => libc::B134,
+        150 => libc::B150,
+        200 => libc::B200,
+        300 => libc::B300,
+        600 => libc::B600,
+        1200 => libc::B1200,
+        1800 => libc::B1800,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        576000 => libc::B576000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        1152000 => libc::B1152000,
+        1500000 => libc::B1500000,
+        2000000 => libc::B2000000,
+        2500000 => libc::B2500000,
+        3000000 => libc::B3000000,
+        3500000 => libc::B3500000,
+        4000000 => libc::B4000000,
+        _ => return None,
+    };
+    Some(speed)
+}
+
+/// Open the serial device and configure it as a raw line with `baudrate`
+pub(crate) fn open_serial_port(path: &Path, baudrate: u32) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+    let fd = file.as_raw_fd();
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::cfmakeraw(&mut termios) };
+    termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    let speed = baud_rate(baudrate).unwrap_or_else(|| {
+        log_warning!("Unsupported baudrate {}, using 115200", baudrate);
+        libc::B115200
+    });
+    if unsafe { libc::cfsetispeed(&mut termios, speed) } < 0
+        || unsafe { libc::cfsetospeed(&mut termios, speed) } < 0
+        || unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Serve the serial line like a client connection. Messages are preceded by
+/// the serial header if `RS232SyncSerialHeader` is set.
+pub(crate) async fn serial_client(file: File, name: String, daemon: SharedDaemon) {
+    let reader = match file.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            log_error!("Cannot read from serial device {}: {}", name, e);
+            return;
+        }
+    };
+    let serial_header = daemon.lock().unwrap().config().rs232_sync_serial_header;
+    handle_client(
+        async_std::fs::File::from(reader),
+        async_std::fs::File::from(file),
+        serial_header,
+        &name,
+        daemon,
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::tests::control_request;
+    use crate::control::DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL;
+    use crate::message::{parse_header, DltMessage, DLT_SERIAL_HEADER};
+    use crate::test_util::{test_daemon, TestDir};
+    use async_std::io::{ReadExt, WriteExt};
+    use std::ffi::CStr;
+    use std::os::unix::io::FromRawFd;
+    use std::path::PathBuf;
+
+    /// Open a pseudo terminal, returns the master and the path of the slave
+    fn open_pty() -> (File, PathBuf) {
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        assert!(master >= 0);
+        assert_eq!(unsafe { libc::grantpt(master) }, 0);
+        assert_eq!(unsafe { libc::unlockpt(master) }, 0);
+        let mut name = [0 as libc::c_char; 64];
+        assert_eq!(
+            unsafe { libc::ptsname_r(master, name.as_mut_ptr(), name.len()) },
+            0
+        );
+        let slave = unsafe { CStr::from_ptr(name.as_ptr()) };
+        let slave = PathBuf::from(slave.to_str().unwrap());
+        (unsafe { File::from_raw_fd(master) }, slave)
+    }
+
+    /// Read a message preceded by the serial header
+    async fn read_message(line: &mut async_std::fs::File) -> Vec<u8> {
+        let mut serial_header = [0u8; 4];
+        line.read_exact(&mut serial_header).await.unwrap();
+        assert_eq!(serial_header, DLT_SERIAL_HEADER);
+        let mut message = vec![0u8; 4];
+        line.read_exact(&mut message).await.unwrap();
+        let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+        message.resize(len, 0);
+        line.read_exact(&mut message[4..]).await.unwrap();
+        message
+    }
+
+    #[test]
+    fn serial_line() {
+        assert_eq!(baud_rate(115200), Some(libc::B115200));
+        assert_eq!(baud_rate(12345), None);
+
+        let (master, slave) = open_pty();
+        let serial = open_serial_port(&slave, 115200).unwrap();
+        let dir = TestDir::new("serial");
+        let daemon = test_daemon(&dir);
+
+        async_std::task::block_on(async {
+            async_std::task::spawn(serial_client(
+                serial,
+                slave.to_string_lossy().into_owned(),
+                daemon.clone(),
+            ));
+            let mut line = async_std::fs::File::from(master);
+            // connection info and the status message of the daemon
+            read_message(&mut line).await;
+            read_message(&mut line).await;
+
+            let message = DltMessage::new(vec![0x20, 0, 0, 8, 1, 2, 3, 4]);
+            daemon.lock().unwrap().forward_message(&message);
+            assert_eq!(read_message(&mut line).await, message.as_bytes());
+
+            line.write_all(&control_request(DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL, &[]))
+                .await
+                .unwrap();
+            line.flush().await.unwrap();
+            let response = read_message(&mut line).await;
+            let offset = parse_header(&response).unwrap().payload_offset;
+            assert_eq!(
+                response[offset..offset + 4],
+                DLT_SERVICE_ID_GET_DEFAULT_LOG_LEVEL.to_le_bytes()
+            );
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use async_std::channel::{self, Receiver};
+use libdlt::error::DltError;
+use std::io;
+
+/// Signals the daemon reacts to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Signal {
+    /// SIGTERM or SIGINT, shut down cleanly
+    Terminate,
+    /// SIGHUP, reload the configuration file
+    Reload,
+}
+
+fn signal_set() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            libc::sigaddset(&mut set, signal);
+        }
+        set
+    }
+}
+
+/// Block the handled signals and deliver them through a channel instead.
+///
+/// Must be called before any threads are started, so every thread inherits
+/// the signal mask and only the waiting thread receives the signals.
+pub(crate) fn handle_signals() -> Result<Receiver<Signal>, DltError> {
+    let set = signal_set();
+    let result = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result).into());
+    }
+
+    let (sender, receiver) = channel::unbounded();
+    std::thread::Builder::new()
+        .name("signals".into())
+        .spawn(move || loop {
+            let mut signal = 0;
+            if unsafe { libc::sigwait(&set, &mut signal) } != 0 {
+                continue;
+            }
+            let signal = match signal {
+                libc::SIGHUP => Signal::Reload,
+                _ => Signal::Terminate,
+            };
+            if sender.send_blocking(signal).is_err() {
+                break;
+            }
+        })?;
+    Ok(receiver)
+}
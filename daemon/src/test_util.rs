@@ -0,0 +1,42 @@
+use crate::daemon::{Daemon, SharedDaemon};
+use libdlt::config::DaemonConfig;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A directory of its own for one test. It is removed with everything in it
+/// when the test ends, also when the test fails.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let dir = std::env::temp_dir().join(format!("dlt-daemon-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A daemon keeping its runtime configuration in `dir`
+pub(crate) fn test_daemon(dir: &Path) -> SharedDaemon {
+    Arc::new(Mutex::new(Daemon::new(DaemonConfig {
+        tcpsync_serial_header: false,
+        persistance_storage_path: dir.to_owned(),
+        ..Default::default()
+    })))
+}
@@ -0,0 +1,69 @@
+use crate::logging::log_error;
+use crate::message::DltMessage;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// Sends every message to a UDP multicast group, for receivers that do not
+/// hold a TCP connection
+pub(crate) struct UdpMulticast {
+    socket: UdpSocket,
+    group: SocketAddr,
+    /// The last send failed, further errors are not reported until one succeeds
+    failing: bool,
+}
+
+impl UdpMulticast {
+    pub fn new(address: IpAddr, port: u16) -> io::Result<Self> {
+        let local: IpAddr = match address {
+            IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let socket = UdpSocket::bind((local, 0))?;
+        // the daemon must never wait for the network
+        socket.set_nonblocking(true)?;
+        Ok(UdpMulticast {
+            socket,
+            group: SocketAddr::new(address, port),
+            failing: false,
+        })
+    }
+
+    pub fn send(&mut self, message: &DltMessage) {
+        match self.socket.send_to(message.as_bytes(), self.group) {
+            Ok(_) => self.failing = false,
+            Err(e) => {
+                if !self.failing {
+                    log_error!("Failed to send UDP multicast to {}: {}", self.group, e);
+                }
+                self.failing = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn multicast_message() {
+        let group = Ipv4Addr::new(225, 0, 0, 37);
+        let receiver = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        receiver
+            .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let port = receiver.local_addr().unwrap().port();
+
+        let mut multicast = UdpMulticast::new(group.into(), port).unwrap();
+        let message = DltMessage::new(vec![0x20, 0, 0, 8, 1, 2, 3, 4]);
+        multicast.send(&message);
+
+        let mut buffer = [0u8; 64];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], message.as_bytes());
+    }
+}
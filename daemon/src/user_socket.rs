@@ -0,0 +1,332 @@
+#[cfg(feature = "unix_socket")]
+use crate::control_socket::bind_unix_socket;
+use crate::daemon::{read_user_messages, SharedDaemon};
+use crate::fifo::send_to_application;
+#[cfg(feature = "unix_socket")]
+use crate::fifo::set_fifo_group;
+use crate::logging::log_error;
+use async_std::channel::{self, Receiver, Sender, TrySendError};
+use async_std::io::{Read, Write, WriteExt};
+use async_std::net::TcpListener;
+#[cfg(feature = "unix_socket")]
+use async_std::os::unix::net::UnixListener;
+use async_std::os::unix::net::UnixStream;
+use async_std::stream::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Messages queued for an application before sending to it fails
+const DLT_DAEMON_APPLICATION_QUEUE_SIZE: usize = 100;
+
+/// Same permissions as the FIFO, applications of the FIFO group may connect
+#[cfg(feature = "unix_socket")]
+const DLT_DAEMON_USER_SOCKET_MODE: u32 = 0o660;
+
+/// Pending VSOCK connections
+const DLT_DAEMON_VSOCK_BACKLOG: libc::c_int = 10;
+
+/// Wait before accepting VSOCK connections again while the daemon is out of
+/// file descriptors or memory
+const DLT_DAEMON_VSOCK_ACCEPT_RETRY: Duration = Duration::from_secs(1);
+
+/// Identifies an application connection, applications registered on it are
+/// reached over it whatever PID they report
+pub(crate) type ConnectionId = u32;
+
+/// The channels control messages reach the applications on: the connection
+/// an application registered on, otherwise its FIFO
+pub(crate) struct UserChannels {
+    /// Not set when the applications connect to the socket instead
+    fifo_directory: Option<PathBuf>,
+    connections: HashMap<ConnectionId, Sender<Vec<u8>>>,
+    next_connection: ConnectionId,
+}
+
+impl UserChannels {
+    pub fn new(fifo_directory: Option<&Path>) -> Self {
+        UserChannels {
+            fifo_directory: fifo_directory.map(Path::to_owned),
+            connections: HashMap::new(),
+            next_connection: 0,
+        }
+    }
+
+    /// Send a message over `connection`, or to the FIFO of the application
+    /// with `pid` if it did not register on a connection. Never blocks, a
+    /// full channel is reported as an error.
+    pub fn send(&self, pid: u32, connection: Option<ConnectionId>, data: &[u8]) -> io::Result<()> {
+        let Some(connection) = connection else {
+            return match &self.fifo_directory {
+                Some(fifo_directory) => send_to_application(fifo_directory, pid, data),
+                None => Err(io::ErrorKind::NotConnected.into()),
+            };
+        };
+        let Some(sender) = self.connections.get(&connection) else {
+            return Err(io::ErrorKind::NotConnected.into());
+        };
+        sender.try_send(data.to_vec()).map_err(|e| match e {
+            TrySendError::Full(_) => io::ErrorKind::WouldBlock.into(),
+            TrySendError::Closed(_) => io::ErrorKind::NotConnected.into(),
+        })
+    }
+
+    /// Add a connection, messages for it are queued on `sender`
+    pub fn add_connection(&mut self, sender: Sender<Vec<u8>>) -> ConnectionId {
+        let connection = self.next_connection;
+        self.next_connection = self.next_connection.wrapping_add(1);
+        self.connections.insert(connection, sender);
+        connection
+    }
+
+    pub fn remove_connection(&mut self, connection: ConnectionId) {
+        self.connections.remove(&connection);
+    }
+}
+
+/// Bind the socket the applications connect to instead of writing to the
+/// FIFO. If `group` is not empty the socket is handed over to that group.
+#[cfg(feature = "unix_socket")]
+pub(crate) fn create_user_socket(path: &Path, group: &str) -> io::Result<UnixListener> {
+    let listener = bind_unix_socket(path, DLT_DAEMON_USER_SOCKET_MODE)?;
+    if !group.is_empty() {
+        set_fifo_group(path, group);
+    }
+    Ok(listener)
+}
+
+/// Accept application connections
+#[cfg(feature = "unix_socket")]
+pub(crate) async fn user_socket_server(listener: UnixListener, daemon: SharedDaemon) {
+    application_server(listener.incoming(), daemon).await
+}
+
+/// Accept applications of containers and virtual machines, which cannot
+/// reach the FIFO
+pub(crate) async fn ipc_tcp_server(listener: TcpListener, daemon: SharedDaemon) {
+    application_server(listener.incoming(), daemon).await
+}
+
+/// Listen for applications of virtual machines on VSOCK `port`
+pub(crate) fn create_vsock_listener(port: u32) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut address: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    address.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    address.svm_port = port;
+    address.svm_cid = libc::VMADDR_CID_ANY;
+    if unsafe {
+        libc::bind(
+            fd,
+            &address as *const libc::sockaddr_vm as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+        )
+    } < 0
+        || unsafe { libc::listen(fd, DLT_DAEMON_VSOCK_BACKLOG) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// Accept VSOCK connections on the calling thread, async-std has no VSOCK
+/// support. The connections are plain byte streams and are served as Unix
+/// streams. Returns if the listener cannot accept connections.
+pub(crate) fn vsock_server(listener: OwnedFd, daemon: SharedDaemon) {
+    loop {
+        let fd = unsafe {
+            libc::accept4(
+                listener.as_raw_fd(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                libc::SOCK_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINTR) => {}
+                Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM) => {
+                    log_error!("Failed to accept VSOCK connection: {}", error);
+                    std::thread::sleep(DLT_DAEMON_VSOCK_ACCEPT_RETRY);
+                }
+                Some(libc::EBADF | libc::EINVAL | libc::ENOTSOCK | libc::EOPNOTSUPP) => {
+                    log_error!("VSOCK server stopped: {}", error);
+                    return;
+                }
+                // the connection failed before it was accepted
+                _ => log_error!("Failed to accept VSOCK connection: {}", error),
+            }
+            continue;
+        }
+        let stream = std::os::unix::net::UnixStream::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        async_std::task::spawn(handle_application(UnixStream::from(stream), daemon.clone()));
+    }
+}
+
+async fn application_server<S, I>(mut incoming: I, daemon: SharedDaemon)
+where
+    S: Read + Write + Clone + Unpin + Send + 'static,
+    I: Stream<Item = io::Result<S>> + Unpin,
+{
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                async_std::task::spawn(handle_application(stream, daemon.clone()));
+            }
+            Err(e) => log_error!("Failed to accept application connection: {}", e),
+        }
+    }
+}
+
+async fn write_to_application<W: Write + Unpin>(mut stream: W, receiver: Receiver<Vec<u8>>) {
+    while let Ok(data) = receiver.recv().await {
+        if let Err(e) = stream.write_all(&data).await {
+            log_error!("Failed to send to application: {}", e);
+            break;
+        }
+    }
+}
+
+/// Process the messages of one connection. Applications registering on it
+/// receive their log levels and injections over the same connection and are
+/// unregistered when it is closed.
+async fn handle_application<S>(stream: S, daemon: SharedDaemon)
+where
+    S: Read + Write + Clone + Unpin + Send + 'static,
+{
+    let (sender, receiver) = channel::bounded(DLT_DAEMON_APPLICATION_QUEUE_SIZE);
+    async_std::task::spawn(write_to_application(stream.clone(), receiver));
+    let connection = daemon
+        .lock()
+        .unwrap()
+        .user_channels_mut()
+        .add_connection(sender);
+    read_user_messages(stream, daemon.clone(), Some(connection)).await;
+    daemon.lock().unwrap().remove_connection(connection);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_daemon, TestDir};
+    use async_std::io::ReadExt;
+    use async_std::net::TcpStream;
+    use dlt_user::user_header::user_control_message::{
+        RegisterApplication, RegisterContext, UserControlMessage,
+    };
+    use dlt_user::user_header::{UserMessage, UserMessageStream};
+    use dlt_user::LogState;
+    use std::net::Ipv4Addr;
+
+    async fn next_message<S: Read + Unpin>(
+        stream: &mut S,
+        messages: &mut UserMessageStream,
+    ) -> UserMessage {
+        loop {
+            if let Some(message) = messages.next_message() {
+                return message;
+            }
+            let mut buffer = [0u8; 64];
+            let len = stream.read(&mut buffer).await.unwrap();
+            messages.feed(&buffer[..len]);
+        }
+    }
+
+    fn assert_log_state(message: UserMessage, log_state: LogState) {
+        match message {
+            UserMessage::LogState(message) => assert_eq!(message.log_state, log_state as i8),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    /// Register a context on `stream` and check the log state and log level
+    /// are sent back on it
+    async fn log_level_on_connection<S>(mut stream: S, daemon: &SharedDaemon)
+    where
+        S: Read + Write + Unpin,
+    {
+        let pid = 4242;
+        let register_application = RegisterApplication {
+            app_id: *b"APP1",
+            pid,
+            description_length: 0,
+        };
+        let register_context = RegisterContext {
+            app_id: *b"APP1",
+            context_id: *b"CON1",
+            log_level_pos: 2,
+            log_level: -1,
+            trace_status: -1,
+            pid,
+            description_length: 0,
+        };
+        let mut request = register_application.to_user_message(&[]);
+        request.extend(register_context.to_user_message(&[]));
+        stream.write_all(&request).await.unwrap();
+
+        let mut messages = UserMessageStream::new();
+        let message = next_message(&mut stream, &mut messages).await;
+        assert_log_state(message, LogState::Disconnected);
+        match next_message(&mut stream, &mut messages).await {
+            UserMessage::LogLevel(log_level) => {
+                assert_eq!({ log_level.log_level_pos }, 2);
+                assert_eq!(log_level.log_level, 4);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // the application is told when the first client connects and when
+        // the last one disconnects
+        let (client, _receiver) = daemon.lock().unwrap().add_client(false);
+        let message = next_message(&mut stream, &mut messages).await;
+        assert_log_state(message, LogState::Connected);
+        daemon.lock().unwrap().remove_client(client);
+        let message = next_message(&mut stream, &mut messages).await;
+        assert_log_state(message, LogState::Disconnected);
+    }
+
+    #[cfg(feature = "unix_socket")]
+    #[test]
+    fn log_level_over_socket() {
+        let dir = TestDir::new("ipc");
+        let path = dir.join("dlt");
+        let daemon = test_daemon(&dir);
+
+        async_std::task::block_on(async {
+            let listener = create_user_socket(&path, "").unwrap();
+            async_std::task::spawn(user_socket_server(listener, daemon.clone()));
+            let stream = UnixStream::connect(&path).await.unwrap();
+            log_level_on_connection(stream, &daemon).await;
+        });
+    }
+
+    #[test]
+    fn log_level_over_tcp() {
+        let dir = TestDir::new("tcp-ipc");
+        let daemon = test_daemon(&dir);
+
+        async_std::task::block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let address = listener.local_addr().unwrap();
+            async_std::task::spawn(ipc_tcp_server(listener, daemon.clone()));
+            let stream = TcpStream::connect(address).await.unwrap();
+            log_level_on_connection(stream, &daemon).await;
+        });
+    }
+
+    #[test]
+    fn vsock_server_stops_on_invalid_listener() {
+        let dir = TestDir::new("vsock");
+        let daemon = test_daemon(&dir);
+        // accepting on a socket that does not listen fails with EINVAL
+        let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM, 0) };
+        assert!(fd >= 0);
+        vsock_server(unsafe { OwnedFd::from_raw_fd(fd) }, daemon);
+    }
+}
@@ -8,6 +8,7 @@ use libdlt::{
 };
 use ringbuf::HeapRb;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicI8, Ordering};
 use std::{
     env,
     fs::File,
@@ -18,16 +19,27 @@ use std::{
     u8,
 };
 use std::{fs, io::Error};
-use user_header::{user_control_message::RegisterApplication, UserHeader, UserMessageType};
+use user_header::{
+    user_control_message::RegisterApplication, UserHeader, UserMessage, UserMessageType,
+};
 
 pub(crate) mod fifo;
 pub(crate) mod log;
 pub(crate) mod mainloop;
-pub(crate) mod user_header;
-enum LogState {
-    Unknown,
-    Disconnected,
-    Connected,
+#[cfg(feature = "shm")]
+pub mod shm;
+pub mod user_header;
+#[cfg(feature = "vm_ipc")]
+pub mod vm_ipc;
+
+/// Whether a client of the daemon receives the logs, as reported by the
+/// daemon. The values are the ones of the `LogState` message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(i8)]
+pub enum LogState {
+    Unknown = -1,
+    Disconnected = 0,
+    Connected = 1,
 }
 
 impl Default for LogState {
@@ -36,6 +48,19 @@ impl Default for LogState {
     }
 }
 
+impl From<i8> for LogState {
+    fn from(value: i8) -> Self {
+        match value {
+            0 => LogState::Disconnected,
+            1 => LogState::Connected,
+            _ => LogState::Unknown,
+        }
+    }
+}
+
+/// Callback registered with `DltUser::register_log_state_callback`
+pub(crate) type LogStateCallback = Arc<dyn Fn(LogState) + Send + Sync>;
+
 pub(crate) fn dlt_user_log_send_register(
     inner: &mut DltUserInner,
     user_header: &UserHeader,
@@ -122,6 +147,18 @@ impl DltUser {
         self.new_context(context_id, description)
             .ok_or(DltError::DltReturnWrongParameter)
     }
+
+    /// Whether a client of the daemon receives the logs. Applications may
+    /// skip expensive formatting while it is `Disconnected`.
+    pub fn log_state(&self) -> LogState {
+        self.inner.lock().unwrap().log_state
+    }
+
+    /// Call `callback` whenever the daemon reports a new log state. It runs
+    /// on the thread of the library.
+    pub fn register_log_state_callback(&self, callback: impl Fn(LogState) + Send + Sync + 'static) {
+        self.inner.lock().unwrap().log_state_callback = Some(Arc::new(callback));
+    }
 }
 
 pub fn dlt_env_extract_ll_set(
@@ -208,6 +245,7 @@ pub struct DltUserInner {
     log_buf_len: u32,
     log_msg_buf_max_size: u32,
     log_state: LogState,
+    log_state_callback: Option<LogStateCallback>,
     contexts: Vec<ContextStore>,
     initial_log_levels: Vec<InitialLogLevel>,
     receiver: channel::Receiver<Message>,
@@ -250,6 +288,7 @@ impl DltUserInner {
             enable_local_print: false,
             local_print_mode: LocalPrintMode::Unset,
             log_state: LogState::default(),
+            log_state_callback: None,
             contexts: Vec::new(),
             initial_log_levels: Vec::new(),
             sender,
@@ -284,8 +323,8 @@ impl DltUserInner {
 
         let inner = ContextInner {
             context_id: context_id_bytes,
-            log_level: 0,
-            trace_status: 1,
+            log_level: AtomicI8::new(0),
+            trace_status: AtomicI8::new(1),
             message_counter: 0,
             description: description.to_owned(),
             sender: self.sender.clone(),
@@ -304,6 +343,44 @@ impl DltUserInner {
             store: context_store,
         })
     }
+
+    /// Process a message the daemon sent to the application. A new log
+    /// state is returned with the callback to call once the lock of the
+    /// library is released.
+    pub(crate) fn handle_daemon_message(
+        &mut self,
+        message: UserMessage,
+    ) -> Option<(LogStateCallback, LogState)> {
+        match message {
+            UserMessage::LogLevel(log_level) => {
+                // the position is the index of the context in the application
+                let pos = log_level.log_level_pos;
+                match usize::try_from(pos)
+                    .ok()
+                    .and_then(|pos| self.contexts.get(pos))
+                {
+                    Some(context) => {
+                        context.set_log_level(log_level.log_level, log_level.trace_status)
+                    }
+                    None => println!("Log level for unknown context {}", pos),
+                }
+            }
+            UserMessage::LogState(log_state) => {
+                self.log_state = LogState::from(log_state.log_state);
+                let callback = self.log_state_callback.clone()?;
+                return Some((callback, self.log_state));
+            }
+            UserMessage::Injection(injection, data) => {
+                println!(
+                    "No callback for injection {:#x} ({} bytes)",
+                    { injection.service_id },
+                    data.len()
+                );
+            }
+            message => println!("Unexpected message from the daemon: {:?}", message),
+        }
+        None
+    }
 }
 
 pub(crate) fn dltinitcommon(
@@ -507,11 +584,22 @@ pub(crate) struct ContextStore {
     inner: Arc<ContextInner>,
 }
 
-#[derive(Clone)]
+impl ContextStore {
+    fn set_log_level(&self, log_level: u8, trace_status: u8) {
+        self.inner
+            .log_level
+            .store(log_level as i8, Ordering::Relaxed);
+        self.inner
+            .trace_status
+            .store(trace_status as i8, Ordering::Relaxed);
+    }
+}
+
 struct ContextInner {
     context_id: [u8; 4],
-    log_level: i8,
-    trace_status: i8,
+    /// Changed by the daemon at any time
+    log_level: AtomicI8,
+    trace_status: AtomicI8,
     message_counter: u8,
     description: String,
     sender: Sender<Message>,
@@ -669,4 +757,40 @@ mod tests {
         assert_eq!(res.unwrap().config.injection_mode, false);
         env::remove_var(key);
     }
+
+    #[test]
+    fn log_level_from_daemon() {
+        let mut dltuserinner = DltUserInner::new(CONFIG).unwrap();
+        dltuserinner.new_context("CTX1", "first").unwrap();
+        let context = dltuserinner.new_context("CTX2", "second").unwrap();
+        let log_level = user_header::user_control_message::LogLevel {
+            log_level: 6,
+            trace_status: 0,
+            log_level_pos: 1,
+        };
+        dltuserinner.handle_daemon_message(UserMessage::LogLevel(log_level));
+        let inner = &context.store.inner;
+        assert_eq!(inner.log_level.load(Ordering::Relaxed), 6);
+        assert_eq!(inner.trace_status.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn log_state_from_daemon() {
+        let mut dltuserinner = DltUserInner::new(CONFIG).unwrap();
+        assert_eq!(dltuserinner.log_state, LogState::Unknown);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        dltuserinner.log_state_callback = Some(Arc::new(move |state| sender.send(state).unwrap()));
+        for log_state in [1, 0] {
+            let message = user_header::user_control_message::LogState { log_state };
+            let (callback, state) = dltuserinner
+                .handle_daemon_message(UserMessage::LogState(message))
+                .unwrap();
+            callback(state);
+        }
+        assert_eq!(dltuserinner.log_state, LogState::Disconnected);
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![LogState::Connected, LogState::Disconnected]
+        );
+    }
 }
@@ -3,46 +3,189 @@ use std::{
     time::Duration,
 };
 
+#[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
 use async_std::fs::OpenOptions;
+#[cfg(feature = "vm_ipc")]
+use async_std::net::TcpStream;
+#[cfg(all(feature = "unix_socket", not(feature = "vm_ipc")))]
+use async_std::os::unix::net::UnixStream;
 
-use crate::DltUserInner;
-use async_std::io::prelude::WriteExt;
+#[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
+use crate::fifo::incoming_fifo;
+#[cfg(feature = "shm")]
+use crate::shm::{SharedMemory, DLT_SHM_NAME};
+use crate::user_header::{UserHeader, UserMessageStream, UserMessageType};
+#[cfg(feature = "vm_ipc")]
+use crate::vm_ipc::{connect_vsock, daemon_address, DaemonAddress};
+use crate::{any_as_u8_slice, DltUserInner};
+use async_std::io::prelude::{ReadExt, WriteExt};
+use async_std::io::Read;
 
-pub(crate) async fn mainloop(dlt_user: Arc<Mutex<DltUserInner>>) {
-    println!("Mainloop processing started");
+/// Path of the daemon FIFO, or of its socket with the `unix_socket` feature
+#[cfg(not(feature = "vm_ipc"))]
+const DLT_DAEMON_PATH: &str = "/tmp/dlt";
 
-    // This is the task for receiving messages from the server
-    async_std::task::spawn(async move {
-        'incoming_outer: loop {
-            //let temp_path = temp
-            println!("Incoming task started");
+/// The channel log messages are sent to the daemon on
+#[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
+type Connection = async_std::fs::File;
+#[cfg(all(feature = "unix_socket", not(feature = "vm_ipc")))]
+type Connection = UnixStream;
+#[cfg(feature = "vm_ipc")]
+type Connection = Box<dyn async_std::io::Write + Send + Unpin>;
+
+/// Open the daemon FIFO, the daemon answers on the FIFO of the application
+#[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
+async fn connect(_dlt_user: &Arc<Mutex<DltUserInner>>) -> std::io::Result<Connection> {
+    OpenOptions::new()
+        .write(true)
+        .create(false)
+        .open(DLT_DAEMON_PATH)
+        .await
+}
 
-            // TODO: Open the incoming FIFO here and loop over it
-            // asynchronously
+/// Connect to the daemon socket, the daemon answers on the same connection
+#[cfg(all(feature = "unix_socket", not(feature = "vm_ipc")))]
+async fn connect(dlt_user: &Arc<Mutex<DltUserInner>>) -> std::io::Result<Connection> {
+    let stream = UnixStream::connect(DLT_DAEMON_PATH).await?;
+    async_std::task::spawn(receive(stream.clone(), dlt_user.clone()));
+    Ok(stream)
+}
 
-            //dlt_user.lock().unwrap().dlt_log_handle = Some(outgoing_fifo().unwrap());
+/// Connect to the daemon outside the VM or container, the daemon answers on
+/// the same connection
+#[cfg(feature = "vm_ipc")]
+async fn connect(dlt_user: &Arc<Mutex<DltUserInner>>) -> std::io::Result<Connection> {
+    match daemon_address() {
+        DaemonAddress::Tcp(address) => {
+            let stream = TcpStream::connect(address).await?;
+            async_std::task::spawn(receive(stream.clone(), dlt_user.clone()));
+            Ok(Box::new(stream))
+        }
+        &DaemonAddress::Vsock { cid, port } => {
+            let stream = async_std::task::spawn_blocking(move || connect_vsock(cid, port)).await?;
+            async_std::task::spawn(receive(stream.clone(), dlt_user.clone()));
+            Ok(Box::new(stream))
+        }
+    }
+}
 
-            async_std::task::sleep(Duration::from_millis(100)).await;
-            // something seriously wrong. Go back to outer loop and
-            // try to connect again
-            continue 'incoming_outer;
+/// Handle the messages of the daemon until the channel is closed
+async fn receive<R: Read + Unpin>(mut reader: R, dlt_user: Arc<Mutex<DltUserInner>>) {
+    let mut messages = UserMessageStream::new();
+    let mut buffer = [0u8; 1024];
+    while let Ok(len) = reader.read(&mut buffer).await {
+        if len == 0 {
+            break;
+        }
+        messages.feed(&buffer[..len]);
+        while let Some(message) = messages.next_message() {
+            // the callback may call back into the library
+            let log_state = dlt_user.lock().unwrap().handle_daemon_message(message);
+            if let Some((callback, log_state)) = log_state {
+                callback(log_state);
+            }
         }
-    });
+    }
+}
+
+/// Create the FIFO of the application and handle the messages the daemon
+/// writes to it
+#[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
+async fn receive_fifo(dlt_user: Arc<Mutex<DltUserInner>>) {
+    use std::os::unix::io::AsRawFd;
+
+    let fifo = loop {
+        match incoming_fifo() {
+            Ok((fifo, path)) => {
+                dlt_user.lock().unwrap().user_path = Some(path);
+                break fifo;
+            }
+            Err(e) => {
+                println!("Cannot create the application FIFO: {:?}", e);
+                async_std::task::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    };
+    // reads run on a blocking thread and wait for the daemon
+    let fd = fifo.as_raw_fd();
+    unsafe {
+        libc::fcntl(
+            fd,
+            libc::F_SETFL,
+            libc::fcntl(fd, libc::F_GETFL) & !libc::O_NONBLOCK,
+        )
+    };
+    receive(async_std::fs::File::from(fifo), dlt_user).await;
+}
+
+/// Store a message in the shared memory and notify the daemon if it has to
+/// start draining it. While the buffer is full the daemon is reminded until
+/// there is room again. A message larger than the whole buffer is sent on the
+/// FIFO instead.
+#[cfg(feature = "shm")]
+async fn write_shm(
+    shm: &SharedMemory,
+    fifo: &mut Connection,
+    message: &[u8],
+) -> std::io::Result<()> {
+    if !shm.fits(message.len()) {
+        let mut bytes = UserHeader::new(UserMessageType::Log).as_bytes().to_vec();
+        bytes.extend_from_slice(message);
+        return fifo.write_all(&bytes).await;
+    }
+    let notification = UserHeader::new(UserMessageType::LogShm);
+    loop {
+        match shm.push(message) {
+            Some(true) => return fifo.write_all(notification.as_bytes()).await,
+            Some(false) => return Ok(()),
+            None => {
+                fifo.write_all(notification.as_bytes()).await?;
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+}
+
+pub(crate) async fn mainloop(dlt_user: Arc<Mutex<DltUserInner>>) {
+    println!("Mainloop processing started");
+
+    // When connected over a socket the messages of the daemon arrive on the
+    // connection instead
+    #[cfg(not(any(feature = "unix_socket", feature = "vm_ipc")))]
+    async_std::task::spawn(receive_fifo(dlt_user.clone()));
 
     let rx = dlt_user.lock().unwrap().receiver.clone();
     'outer: loop {
         // attempt to connect to the daemon
-        if let Ok(mut file) = OpenOptions::new()
-            .write(true)
-            .create(false)
-            .open("/tmp/dlt")
-            .await
-        {
+        if let Ok(mut file) = connect(&dlt_user).await {
             println!("Connect successful");
+            // the daemon creates the shared memory before the FIFO
+            #[cfg(feature = "shm")]
+            let shm = match SharedMemory::open(DLT_SHM_NAME) {
+                Ok(shm) => Some(shm),
+                Err(e) => {
+                    println!("Shared memory not available ({}), logging to the FIFO", e);
+                    None
+                }
+            };
             loop {
                 // wait for messages that need to be sent
-                if let Ok(message) = rx.recv().await {
-                    let bytes = message.as_bytes();
+                if let Ok(mut message) = rx.recv().await {
+                    // The daemon adds the storage header, the user header tells it
+                    // that a DLT message follows
+                    message.storage_header = None;
+                    #[cfg(feature = "shm")]
+                    if let Some(shm) = &shm {
+                        if let Err(_e) = write_shm(shm, &mut file, &message.as_bytes()).await {
+                            println!("Error notifying the daemon");
+                            async_std::task::sleep(Duration::from_millis(100)).await;
+                            continue 'outer;
+                        }
+                        continue;
+                    }
+                    let mut bytes =
+                        any_as_u8_slice(&UserHeader::new(UserMessageType::Log)).to_vec();
+                    bytes.extend_from_slice(&message.as_bytes());
                     if let Err(_e) = file.write_all(bytes.as_slice()).await {
                         println!("Error writing message to file");
                         async_std::task::sleep(Duration::from_millis(100)).await;
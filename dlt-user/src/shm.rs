@@ -0,0 +1,295 @@
+//! Ring buffer in POSIX shared memory, written by the applications and
+//! drained by the daemon. The FIFO only carries a `LogShm` notification when
+//! the buffer turns non-empty.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Name of the shared memory segment created by the daemon
+pub const DLT_SHM_NAME: &str = "/dlt-shm";
+
+/// The daemon and the applications of its FIFO group may log, like the FIFO
+const DLT_SHM_MODE: libc::mode_t = 0o660;
+
+/// Length prefix stored in front of every message
+const LENGTH_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Start of the segment, the data area follows
+#[repr(C)]
+struct ShmHeader {
+    /// Process shared and robust, guards the rest of the segment
+    mutex: libc::pthread_mutex_t,
+    /// Size of the data area
+    size: u32,
+    /// Offset the next message is written to
+    write: u32,
+    /// Offset of the oldest message
+    read: u32,
+    /// Number of stored messages
+    count: u32,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<ShmHeader>();
+
+impl ShmHeader {
+    fn reset(&mut self) {
+        self.write = 0;
+        self.read = 0;
+        self.count = 0;
+    }
+
+    fn used(&self) -> usize {
+        if self.count == 0 {
+            0
+        } else if self.write > self.read {
+            (self.write - self.read) as usize
+        } else {
+            (self.size - self.read + self.write) as usize
+        }
+    }
+}
+
+/// Copy `bytes` to `offset`, continuing at the start of `data` at its end.
+/// Returns the offset following the copied bytes.
+fn write_wrapping(data: &mut [u8], offset: usize, bytes: &[u8]) -> usize {
+    let first = bytes.len().min(data.len() - offset);
+    data[offset..offset + first].copy_from_slice(&bytes[..first]);
+    data[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+    (offset + bytes.len()) % data.len()
+}
+
+/// Counterpart of `write_wrapping`, fills `bytes` starting at `offset`
+fn read_wrapping(data: &[u8], offset: usize, bytes: &mut [u8]) -> usize {
+    let first = bytes.len().min(data.len() - offset);
+    bytes[..first].copy_from_slice(&data[offset..offset + first]);
+    let rest = bytes.len() - first;
+    bytes[first..].copy_from_slice(&data[..rest]);
+    (offset + bytes.len()) % data.len()
+}
+
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// A mapping of the shared memory segment. Messages are stored as a native
+/// endian `u32` length followed by the message.
+pub struct SharedMemory {
+    header: *mut ShmHeader,
+    len: usize,
+}
+
+// Safety: all access to the mapping goes through the process shared mutex
+unsafe impl Send for SharedMemory {}
+
+impl SharedMemory {
+    /// Create the segment with a total size of `size` bytes, replacing a
+    /// segment left behind by a previous daemon
+    pub fn create(name: &str, size: usize) -> io::Result<Self> {
+        if size <= HEADER_SIZE + LENGTH_SIZE || size - HEADER_SIZE > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid shared memory size {size}"),
+            ));
+        }
+        let name = shm_name(name)?;
+        unsafe { libc::shm_unlink(name.as_ptr()) };
+        let fd = unsafe {
+            libc::shm_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR | libc::O_CLOEXEC,
+                DLT_SHM_MODE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // the mode passed to shm_open is restricted by the umask
+        if unsafe { libc::fchmod(fd.as_raw_fd(), DLT_SHM_MODE) } < 0
+            || unsafe { libc::ftruncate(fd.as_raw_fd(), size as libc::off_t) } < 0
+        {
+            let error = io::Error::last_os_error();
+            unsafe { libc::shm_unlink(name.as_ptr()) };
+            return Err(error);
+        }
+        let shm = Self::map(&fd, size)?;
+
+        let header = unsafe { &mut *shm.header };
+        let mut attr: libc::pthread_mutexattr_t = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::pthread_mutexattr_init(&mut attr);
+            libc::pthread_mutexattr_setpshared(&mut attr, libc::PTHREAD_PROCESS_SHARED);
+            libc::pthread_mutexattr_setrobust(&mut attr, libc::PTHREAD_MUTEX_ROBUST);
+        }
+        let result = unsafe { libc::pthread_mutex_init(&mut header.mutex, &attr) };
+        unsafe { libc::pthread_mutexattr_destroy(&mut attr) };
+        if result != 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        header.size = (size - HEADER_SIZE) as u32;
+        header.reset();
+        Ok(shm)
+    }
+
+    /// Map the segment created by the daemon
+    pub fn open(name: &str) -> io::Result<Self> {
+        let name = shm_name(name)?;
+        let fd = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = stat.st_size as usize;
+        if len <= HEADER_SIZE + LENGTH_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory not initialized",
+            ));
+        }
+        let shm = Self::map(&fd, len)?;
+        if unsafe { (*shm.header).size } as usize != len - HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared memory not initialized",
+            ));
+        }
+        Ok(shm)
+    }
+
+    /// Remove the segment, mappings stay valid until they are dropped
+    pub fn unlink(name: &str) -> io::Result<()> {
+        let name = shm_name(name)?;
+        if unsafe { libc::shm_unlink(name.as_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn map(fd: &OwnedFd, len: usize) -> io::Result<Self> {
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if address == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(SharedMemory {
+            header: address as *mut ShmHeader,
+            len,
+        })
+    }
+
+    /// Run `f` on the header and the data area while holding the mutex. If a
+    /// process died while holding it, the buffer may be inconsistent and is
+    /// emptied.
+    fn with_lock<T>(&self, f: impl FnOnce(&mut ShmHeader, &mut [u8]) -> T) -> T {
+        let header = unsafe { &mut *self.header };
+        let result = unsafe { libc::pthread_mutex_lock(&mut header.mutex) };
+        if result == libc::EOWNERDEAD {
+            unsafe { libc::pthread_mutex_consistent(&mut header.mutex) };
+            header.reset();
+        }
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(
+                (self.header as *mut u8).add(HEADER_SIZE),
+                self.len - HEADER_SIZE,
+            )
+        };
+        let value = f(header, data);
+        unsafe { libc::pthread_mutex_unlock(&mut header.mutex) };
+        value
+    }
+
+    /// Whether a message of `len` bytes fits into the empty buffer
+    pub fn fits(&self, len: usize) -> bool {
+        LENGTH_SIZE + len <= self.len - HEADER_SIZE
+    }
+
+    /// Append a message. Returns `None` if there is no room for it, otherwise
+    /// whether the buffer was empty, in which case the daemon has to be
+    /// notified.
+    pub fn push(&self, message: &[u8]) -> Option<bool> {
+        self.with_lock(|header, data| {
+            if header.used() + LENGTH_SIZE + message.len() > data.len() {
+                return None;
+            }
+            let length = (message.len() as u32).to_ne_bytes();
+            let offset = write_wrapping(data, header.write as usize, &length);
+            header.write = write_wrapping(data, offset, message) as u32;
+            header.count += 1;
+            Some(header.count == 1)
+        })
+    }
+
+    /// Remove the oldest message
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        self.with_lock(|header, data| {
+            if header.count == 0 {
+                return None;
+            }
+            let mut length = [0u8; LENGTH_SIZE];
+            let offset = read_wrapping(data, header.read as usize, &mut length);
+            let length = u32::from_ne_bytes(length) as usize;
+            if LENGTH_SIZE + length > header.used() {
+                println!("Shared memory corrupted, {} messages lost", header.count);
+                header.reset();
+                return None;
+            }
+            let mut message = vec![0u8; length];
+            header.read = read_wrapping(data, offset, &mut message) as u32;
+            header.count -= 1;
+            if header.count == 0 {
+                header.reset();
+            }
+            Some(message)
+        })
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.header as *mut libc::c_void, self.len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_pop() {
+        let name = format!("/dlt-shm-test-{}", std::process::id());
+        let daemon = SharedMemory::create(&name, HEADER_SIZE + 32).unwrap();
+        let application = SharedMemory::open(&name).unwrap();
+
+        assert_eq!(application.push(&[1; 6]), Some(true));
+        assert_eq!(application.push(&[2; 10]), Some(false));
+        assert_eq!(daemon.pop(), Some(vec![1; 6]));
+
+        // wraps around the end of the data area
+        assert_eq!(application.push(&[3; 12]), Some(false));
+        // 30 of 32 bytes used
+        assert_eq!(application.push(&[4; 1]), None);
+        assert_eq!(daemon.pop(), Some(vec![2; 10]));
+        assert_eq!(daemon.pop(), Some(vec![3; 12]));
+        assert_eq!(daemon.pop(), None);
+        assert!(application.fits(28));
+        assert!(!application.fits(29));
+        assert_eq!(application.push(&[5; 28]), Some(true));
+        assert_eq!(daemon.pop(), Some(vec![5; 28]));
+
+        SharedMemory::unlink(&name).unwrap();
+        assert!(SharedMemory::open(&name).is_err());
+    }
+}
@@ -1,5 +1,9 @@
-#[derive(Debug)]
+use user_control_message::{
+    AppLogLevelTraceStatus, BufferOverflow, Injection, LogMode, RegisterApplication,
+    RegisterContext, UnRegisterContext, UnregisterApplication, UserControlMessage,
+};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UserMessageType {
     Log,
     RegisterApplication,
@@ -36,6 +40,29 @@ impl From<UserMessageType> for u32 {
         }
     }
 }
+impl From<u32> for UserMessageType {
+    fn from(value: u32) -> UserMessageType {
+        match value {
+            1 => UserMessageType::Log,
+            2 => UserMessageType::RegisterApplication,
+            3 => UserMessageType::UnRegisterApplication,
+            4 => UserMessageType::RegisterContext,
+            5 => UserMessageType::UnRegisterContext,
+            6 => UserMessageType::LogLevel,
+            7 => UserMessageType::Injection,
+            8 => UserMessageType::OverFlow,
+            9 => UserMessageType::AppLlTs,
+            10 => UserMessageType::LogShm,
+            11 => UserMessageType::LogMode,
+            12 => UserMessageType::LogState,
+            13 => UserMessageType::Marker,
+            _ => UserMessageType::NotSupported,
+        }
+    }
+}
+
+/// Pattern that starts every message exchanged between the user library and the daemon
+pub const USER_HEADER_PATTERN: [u8; 4] = [b'D', b'U', b'H', 1];
 #[derive(Clone, Copy, Debug)]
 #[repr(C, packed)]
 pub struct UserHeader {
@@ -49,11 +76,20 @@ impl UserHeader {
             message_type: message_type.into(),
         }
     }
+
+    pub fn message_type(&self) -> UserMessageType {
+        UserMessageType::from(self.message_type)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        crate::any_as_u8_slice(self)
+    }
 }
 pub mod user_control_message {
     use std::process;
 
-    use crate::DltUserInner;
+    use super::{UserHeader, UserMessageType};
+    use crate::{any_as_u8_slice, DltUserInner};
 
     pub(crate) fn opt_string_to_u8_4(str: Option<String>) -> [u8; 4] {
         let mut result = [0u8; 4];
@@ -64,9 +100,41 @@ pub mod user_control_message {
         }
         result
     }
+
+    /// Control messages are `repr(C, packed)` structs that are exchanged as raw
+    /// bytes, with the same layout as the `DltUserControlMsg*` structs of the C library.
+    pub trait UserControlMessage: Copy + Sized {
+        const MESSAGE_TYPE: UserMessageType;
+
+        fn as_bytes(&self) -> &[u8] {
+            any_as_u8_slice(self)
+        }
+
+        fn from_bytes(bytes: &[u8]) -> Option<Self> {
+            if bytes.len() < std::mem::size_of::<Self>() {
+                return None;
+            }
+            // Safety: the implementors are packed plain-old-data structs, so every
+            // bit pattern is valid and there are no alignment requirements.
+            Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+        }
+
+        /// Serialize the message with its user header and any trailing payload
+        fn to_user_message(&self, payload: &[u8]) -> Vec<u8> {
+            let user_header = UserHeader::new(Self::MESSAGE_TYPE);
+            let mut buffer = Vec::with_capacity(
+                std::mem::size_of::<UserHeader>() + std::mem::size_of::<Self>() + payload.len(),
+            );
+            buffer.extend_from_slice(any_as_u8_slice(&user_header));
+            buffer.extend_from_slice(self.as_bytes());
+            buffer.extend_from_slice(payload);
+            buffer
+        }
+    }
+
     #[derive(Clone, Copy, Debug)]
     #[repr(C, packed)]
-    pub(crate) struct RegisterApplication {
+    pub struct RegisterApplication {
         pub app_id: [u8; 4],
         pub pid: u32,
         pub description_length: u32,
@@ -80,10 +148,15 @@ pub mod user_control_message {
             }
         }
     }
+    impl UserControlMessage for RegisterApplication {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::RegisterApplication;
+    }
+
+    #[derive(Clone, Copy, Debug)]
     #[repr(C, packed)]
     pub struct UnregisterApplication {
-        app_id: [u8; 4],
-        pid: u32,
+        pub app_id: [u8; 4],
+        pub pid: u32,
     }
     impl UnregisterApplication {
         fn new(dltuserinner: &DltUserInner) -> Self {
@@ -93,15 +166,20 @@ pub mod user_control_message {
             }
         }
     }
+    impl UserControlMessage for UnregisterApplication {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::UnRegisterApplication;
+    }
+
+    #[derive(Clone, Copy, Debug)]
     #[repr(C, packed)]
     pub struct RegisterContext {
-        app_id: [u8; 4],
-        context_id: [u8; 4],
-        log_level_pos: i32,
-        log_level: i32,
-        trace_status: i8,
-        pid: u32,
-        description_length: u32,
+        pub app_id: [u8; 4],
+        pub context_id: [u8; 4],
+        pub log_level_pos: i32,
+        pub log_level: i8,
+        pub trace_status: i8,
+        pub pid: u32,
+        pub description_length: u32,
     }
     impl RegisterContext {
         fn new(dltuserinner: &DltUserInner) -> Self {
@@ -116,12 +194,16 @@ pub mod user_control_message {
             }
         }
     }
+    impl UserControlMessage for RegisterContext {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::RegisterContext;
+    }
 
+    #[derive(Clone, Copy, Debug)]
     #[repr(C, packed)]
     pub struct UnRegisterContext {
-        app_id: [u8; 4],
-        context_id: [u8; 4],
-        pid: u32,
+        pub app_id: [u8; 4],
+        pub context_id: [u8; 4],
+        pub pid: u32,
     }
     impl UnRegisterContext {
         fn new(dltuserinner: &DltUserInner) -> Self {
@@ -132,12 +214,246 @@ pub mod user_control_message {
             }
         }
     }
+    impl UserControlMessage for UnRegisterContext {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::UnRegisterContext;
+    }
+
+    /// Sent by the daemon to change the log level of a single context
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct LogLevel {
+        pub log_level: u8,
+        pub trace_status: u8,
+        pub log_level_pos: i32,
+    }
+    impl UserControlMessage for LogLevel {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::LogLevel;
+    }
+
+    /// Sent by the daemon to forward an injection, followed by `data_length_inject` bytes
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct Injection {
+        pub log_level_pos: i32,
+        pub service_id: u32,
+        pub data_length_inject: u32,
+    }
+    impl UserControlMessage for Injection {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::Injection;
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct BufferOverflow {
+        pub overflow_counter: u32,
+        pub app_id: [u8; 4],
+    }
+    impl UserControlMessage for BufferOverflow {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::OverFlow;
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct AppLogLevelTraceStatus {
+        pub app_id: [u8; 4],
+        pub log_level: u8,
+        pub trace_status: u8,
+    }
+    impl UserControlMessage for AppLogLevelTraceStatus {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::AppLlTs;
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct LogMode {
+        pub log_mode: i8,
+    }
+    impl UserControlMessage for LogMode {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::LogMode;
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    #[repr(C, packed)]
+    pub struct LogState {
+        pub log_state: i8,
+    }
+    impl UserControlMessage for LogState {
+        const MESSAGE_TYPE: UserMessageType = UserMessageType::LogState;
+    }
+}
+
+/// A decoded message as received from the FIFO
+#[derive(Debug)]
+pub enum UserMessage {
+    /// A complete DLT message, starting at the standard header
+    Log(Vec<u8>),
+    RegisterApplication(RegisterApplication, String),
+    UnRegisterApplication(UnregisterApplication),
+    RegisterContext(RegisterContext, String),
+    UnRegisterContext(UnRegisterContext),
+    LogLevel(user_control_message::LogLevel),
+    Injection(Injection, Vec<u8>),
+    OverFlow(BufferOverflow),
+    AppLlTs(AppLogLevelTraceStatus),
+    LogShm,
+    LogMode(LogMode),
+    LogState(user_control_message::LogState),
+    Marker,
+    NotSupported(u32),
+}
+
+/// Splits a byte stream into user messages. Bytes in front of a `DUH\x01`
+/// pattern are skipped, so the stream resynchronizes after garbage.
+#[derive(Default)]
+pub struct UserMessageStream {
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl UserMessageStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Number of bytes that have been fed but not consumed yet
+    pub fn pending(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    /// Returns the next complete message, or `None` if more data is needed
+    pub fn next_message(&mut self) -> Option<UserMessage> {
+        const HEADER_LEN: usize = std::mem::size_of::<UserHeader>();
+        loop {
+            let available = &self.buffer[self.start..];
+            match available
+                .windows(USER_HEADER_PATTERN.len())
+                .position(|window| window == USER_HEADER_PATTERN)
+            {
+                Some(offset) => self.start += offset,
+                None => {
+                    // keep a possibly incomplete pattern at the end of the buffer
+                    self.start += available
+                        .len()
+                        .saturating_sub(USER_HEADER_PATTERN.len() - 1);
+                    return None;
+                }
+            }
+
+            let available = &self.buffer[self.start..];
+            if available.len() < HEADER_LEN {
+                return None;
+            }
+            let message_type = u32::from_ne_bytes(available[4..8].try_into().unwrap());
+            match decode_body(message_type, &available[HEADER_LEN..]) {
+                Decoded::Message(len, message) => {
+                    self.start += HEADER_LEN + len;
+                    return Some(message);
+                }
+                Decoded::Incomplete => return None,
+                Decoded::Invalid => {
+                    // drop the header and look for the next one
+                    self.start += HEADER_LEN;
+                }
+            }
+        }
+    }
+}
+
+enum Decoded {
+    Message(usize, UserMessage),
+    Incomplete,
+    Invalid,
+}
+
+fn decode_with_payload<T: UserControlMessage>(
+    body: &[u8],
+    payload_length: impl Fn(&T) -> usize,
+    wrap: impl Fn(T, &[u8]) -> UserMessage,
+) -> Decoded {
+    let size = std::mem::size_of::<T>();
+    match T::from_bytes(body) {
+        Some(message) => {
+            let total = size + payload_length(&message);
+            if body.len() < total {
+                Decoded::Incomplete
+            } else {
+                Decoded::Message(total, wrap(message, &body[size..total]))
+            }
+        }
+        None => Decoded::Incomplete,
+    }
+}
+
+fn decode_fixed<T: UserControlMessage>(body: &[u8], wrap: impl Fn(T) -> UserMessage) -> Decoded {
+    decode_with_payload(body, |_: &T| 0, |message, _| wrap(message))
+}
+
+fn description(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+fn decode_body(message_type: u32, body: &[u8]) -> Decoded {
+    match UserMessageType::from(message_type) {
+        UserMessageType::Log => {
+            // the length field of the standard header covers the whole message
+            if body.len() < 4 {
+                return Decoded::Incomplete;
+            }
+            let len = u16::from_be_bytes([body[2], body[3]]) as usize;
+            if len < 4 {
+                Decoded::Invalid
+            } else if body.len() < len {
+                Decoded::Incomplete
+            } else {
+                Decoded::Message(len, UserMessage::Log(body[..len].to_vec()))
+            }
+        }
+        UserMessageType::RegisterApplication => decode_with_payload(
+            body,
+            |m: &RegisterApplication| m.description_length as usize,
+            |m, payload| UserMessage::RegisterApplication(m, description(payload)),
+        ),
+        UserMessageType::UnRegisterApplication => {
+            decode_fixed(body, UserMessage::UnRegisterApplication)
+        }
+        UserMessageType::RegisterContext => decode_with_payload(
+            body,
+            |m: &RegisterContext| m.description_length as usize,
+            |m, payload| UserMessage::RegisterContext(m, description(payload)),
+        ),
+        UserMessageType::UnRegisterContext => decode_fixed(body, UserMessage::UnRegisterContext),
+        UserMessageType::LogLevel => decode_fixed(body, UserMessage::LogLevel),
+        UserMessageType::Injection => decode_with_payload(
+            body,
+            |m: &Injection| m.data_length_inject as usize,
+            |m, payload| UserMessage::Injection(m, payload.to_vec()),
+        ),
+        UserMessageType::OverFlow => decode_fixed(body, UserMessage::OverFlow),
+        UserMessageType::AppLlTs => decode_fixed(body, UserMessage::AppLlTs),
+        UserMessageType::LogShm => Decoded::Message(0, UserMessage::LogShm),
+        UserMessageType::LogMode => decode_fixed(body, UserMessage::LogMode),
+        UserMessageType::LogState => decode_fixed(body, UserMessage::LogState),
+        UserMessageType::Marker => Decoded::Message(0, UserMessage::Marker),
+        UserMessageType::NotSupported => {
+            Decoded::Message(0, UserMessage::NotSupported(message_type))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{dlt_user, user_header::user_control_message::RegisterApplication};
+    use crate::{any_as_u8_slice, dlt_user};
 
     #[test]
     fn user_header() {
@@ -154,4 +470,59 @@ mod tests {
         assert_eq!(register_application.app_id, [69, 67, 85, 49]);
         assert!(register_application.description_length == 18);
     }
+
+    #[test]
+    fn stream_decodes_messages() {
+        let register_application = RegisterApplication {
+            app_id: *b"APP1",
+            pid: 42,
+            description_length: 4,
+        };
+        let unregister_context = UnRegisterContext {
+            app_id: *b"APP1",
+            context_id: *b"CTX1",
+            pid: 42,
+        };
+        let mut bytes = b"garbage".to_vec();
+        bytes.extend(register_application.to_user_message(b"Test"));
+        bytes.extend(unregister_context.to_user_message(&[]));
+
+        let mut stream = UserMessageStream::new();
+        // feed the first message in two parts
+        stream.feed(&bytes[..12]);
+        assert!(stream.next_message().is_none());
+        stream.feed(&bytes[12..]);
+
+        match stream.next_message() {
+            Some(UserMessage::RegisterApplication(message, description)) => {
+                assert_eq!(message.app_id, *b"APP1");
+                assert_eq!({ message.pid }, 42);
+                assert_eq!(description, "Test");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        match stream.next_message() {
+            Some(UserMessage::UnRegisterContext(message)) => {
+                assert_eq!(message.context_id, *b"CTX1");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(stream.next_message().is_none());
+        assert_eq!(stream.pending(), 0);
+    }
+
+    #[test]
+    fn stream_decodes_log_message() {
+        // standard header only: htyp, counter, length 8, 4 bytes of payload
+        let dlt_message = [0x20, 0x00, 0x00, 0x08, 1, 2, 3, 4];
+        let mut bytes = any_as_u8_slice(&UserHeader::new(UserMessageType::Log)).to_vec();
+        bytes.extend_from_slice(&dlt_message);
+
+        let mut stream = UserMessageStream::new();
+        stream.feed(&bytes);
+        match stream.next_message() {
+            Some(UserMessage::Log(message)) => assert_eq!(message, dlt_message),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
 }
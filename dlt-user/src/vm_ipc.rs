@@ -0,0 +1,107 @@
+//! Connection to a daemon outside the VM or container of the application,
+//! over TCP or VSOCK. The daemon is taken from `DLT_DAEMON_IPC_ADDRESS`,
+//! either `tcp:<host>:<port>` or `vsock:<cid>:<port>`.
+
+use async_std::os::unix::net::UnixStream;
+use std::env;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::sync::OnceLock;
+
+/// VSOCK port the daemon is expected on, same as the C daemon
+pub const DLT_VSOCK_PORT: u32 = 13490;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum DaemonAddress {
+    /// `host:port`, resolved on every connect
+    Tcp(String),
+    Vsock {
+        cid: u32,
+        port: u32,
+    },
+}
+
+impl Default for DaemonAddress {
+    /// The daemon of the VM host
+    fn default() -> Self {
+        DaemonAddress::Vsock {
+            cid: libc::VMADDR_CID_HOST,
+            port: DLT_VSOCK_PORT,
+        }
+    }
+}
+
+impl DaemonAddress {
+    pub fn parse(address: &str) -> Option<Self> {
+        let (transport, address) = address.split_once(':')?;
+        match transport {
+            "tcp" => Some(DaemonAddress::Tcp(address.to_owned())),
+            "vsock" => {
+                let (cid, port) = address.split_once(':')?;
+                Some(DaemonAddress::Vsock {
+                    cid: cid.parse().ok()?,
+                    port: port.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The address configured in the environment, read once
+pub(crate) fn daemon_address() -> &'static DaemonAddress {
+    static ADDRESS: OnceLock<DaemonAddress> = OnceLock::new();
+    ADDRESS.get_or_init(|| match env::var("DLT_DAEMON_IPC_ADDRESS") {
+        Ok(value) => DaemonAddress::parse(&value).unwrap_or_else(|| {
+            println!("Invalid DLT_DAEMON_IPC_ADDRESS {value}, using the VM host");
+            DaemonAddress::default()
+        }),
+        Err(_) => DaemonAddress::default(),
+    })
+}
+
+/// Connect to the daemon on VSOCK `cid` and `port`. async-std has no VSOCK
+/// support, the connection is a plain byte stream and used as a Unix stream.
+pub(crate) fn connect_vsock(cid: u32, port: u32) -> io::Result<UnixStream> {
+    let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut address: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+    address.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+    address.svm_port = port;
+    address.svm_cid = cid;
+    if unsafe {
+        libc::connect(
+            fd,
+            &address as *const libc::sockaddr_vm as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+        )
+    } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(UnixStream::from(std::os::unix::net::UnixStream::from(
+        socket,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_address() {
+        assert_eq!(
+            DaemonAddress::parse("tcp:10.0.2.2:3495"),
+            Some(DaemonAddress::Tcp("10.0.2.2:3495".into()))
+        );
+        assert_eq!(
+            DaemonAddress::parse("vsock:2:13490"),
+            Some(DaemonAddress::default())
+        );
+        assert_eq!(DaemonAddress::parse("vsock:2"), None);
+        assert_eq!(DaemonAddress::parse("udp:10.0.2.2:3495"), None);
+    }
+}
@@ -4,7 +4,7 @@ use ini::configparser::ini::Ini;
 
 use crate::error::DltError;
 //LOG_EMERG = 0, LOG_ALERT = 1, LOG_CRIT = 2, LOG_ERR = 3, LOG_WARNING = 4, LOG_NOTICE = 5, LOG_INFO = 6, LOG_DEBUG = 7
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SendContextRegistrationOption {
     Apid = 3,
     Loglevel = 4,
@@ -17,7 +17,7 @@ impl SendContextRegistrationOption {
     pub const TS: SendContextRegistrationOption = SendContextRegistrationOption::Ll;
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub enum LogLevel {
     Emergency,
     Alert,
@@ -29,14 +29,14 @@ pub enum LogLevel {
     Debug,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum DaemonLoggingMode {
     Stdout,
     Syslog,
     StdError,
     File(PathBuf),
 }
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DltLogLevel {
     DltLogOff,
     DltLogFatal,
@@ -73,6 +73,7 @@ pub struct DaemonConfig {
     pub context_log_level: DltLogLevel,
     pub context_trace_status: bool,
     pub force_context_loglevel_and_tracestatus: bool,
+    pub app_id_log_level_file: Option<PathBuf>,
     pub injection_mode: bool,
     //Gateway
     pub gateway_mode: bool,
@@ -94,7 +95,9 @@ pub struct DaemonConfig {
     pub send_ecusoftware_version: u32,
     pub path_to_ecusoftware_version: Option<PathBuf>,
     pub send_timezone: u32,
-    pub offline_logstorage_max_devices: bool,
+    /// Devices connected with `dlt-logstorage-ctrl`, the directory of
+    /// `OfflineLogstorageDirPath` does not count. 0 disables logstorage.
+    pub offline_logstorage_max_devices: u32,
     pub offline_logstorage_dir_path: Option<PathBuf>,
     pub offline_logstorage_timestamp: bool,
     pub offline_logstorage_delimiter: String,
@@ -104,6 +107,15 @@ pub struct DaemonConfig {
     pub udpmulticast_ipaddress: IpAddr,
     pub udpmulticast_ipport: u32,
     pub bind_address: IpAddr,
+    pub pid_file: PathBuf,
+    pub daemon_user: Option<String>,
+    pub daemon_group: Option<String>,
+    pub ipc_tcp_port: Option<u16>,
+    pub ipc_tcp_bind_address: IpAddr,
+    pub ipc_vsock_port: Option<u32>,
+    // Only set on the command line
+    pub fifo_directory: PathBuf,
+    pub port: u16,
 }
 
 impl Default for DaemonConfig {
@@ -129,10 +141,11 @@ impl Default for DaemonConfig {
             context_log_level: DltLogLevel::DltLogInfo,
             context_trace_status: false,
             force_context_loglevel_and_tracestatus: false,
+            app_id_log_level_file: None,
             injection_mode: true,
             gateway_mode: false,
             gateway_config_file: PathBuf::from("/etc/dlt_gateway.conf"),
-            daemon_fifo_group: String::from("/tmp/dlt"),
+            daemon_fifo_group: String::new(),
             control_socket_path: PathBuf::from("/tmp/dlt-ctrl.sock"),
             offline_trace_directory: None,
             offline_trace_file_size: 1000000,
@@ -148,7 +161,7 @@ impl Default for DaemonConfig {
             send_ecusoftware_version: 0,
             path_to_ecusoftware_version: None,
             send_timezone: 0,
-            offline_logstorage_max_devices: false,
+            offline_logstorage_max_devices: 0,
             offline_logstorage_dir_path: None,
             offline_logstorage_timestamp: true,
             offline_logstorage_delimiter: String::from("_"),
@@ -158,6 +171,14 @@ impl Default for DaemonConfig {
             udpmulticast_ipaddress: IpAddr::from_str("225.0.0.37").unwrap(),
             udpmulticast_ipport: 3491,
             bind_address: IpAddr::from_str("0.0.0.0").unwrap(),
+            pid_file: PathBuf::from("/tmp/dlt-daemon.pid"),
+            daemon_user: None,
+            daemon_group: None,
+            ipc_tcp_port: None,
+            ipc_tcp_bind_address: IpAddr::from_str("127.0.0.1").unwrap(),
+            ipc_vsock_port: None,
+            fifo_directory: PathBuf::from("/tmp"),
+            port: 3490,
         }
     }
 }
@@ -323,6 +344,13 @@ impl DaemonConfig {
                                         conf.force_context_loglevel_and_tracestatus = true;
                                     }
                                 }
+                                ("contextloglevelpreconfig", Some(value)) => {
+                                    if value.is_empty() {
+                                        conf.app_id_log_level_file = None;
+                                    } else {
+                                        conf.app_id_log_level_file = Some(PathBuf::from(value));
+                                    }
+                                }
                                 ("injectionmode", Some(value)) => {
                                     let val: u32 = value.parse().unwrap();
                                     if val == 0 {
@@ -443,11 +471,7 @@ impl DaemonConfig {
                                 }
                                 ("offlinelogstoragemaxdevices", Some(value)) => {
                                     let val: u32 = value.parse().unwrap();
-                                    if val == 0 {
-                                        conf.offline_logstorage_max_devices = false;
-                                    } else {
-                                        conf.offline_logstorage_max_devices = true;
-                                    }
+                                    conf.offline_logstorage_max_devices = val;
                                 }
                                 ("offlinelogstoragedirpath", Some(value)) => {
                                     if value.is_empty() {
@@ -503,6 +527,37 @@ impl DaemonConfig {
                                         conf.bind_address = ip;
                                     }
                                 }
+                                ("pidfile", Some(value)) => {
+                                    let val: PathBuf = value.parse().unwrap();
+                                    conf.pid_file = val;
+                                }
+                                ("daemonuser", Some(value)) => {
+                                    if value.is_empty() {
+                                        conf.daemon_user = None;
+                                    } else {
+                                        conf.daemon_user = Some(String::from(value));
+                                    }
+                                }
+                                ("daemongroup", Some(value)) => {
+                                    if value.is_empty() {
+                                        conf.daemon_group = None;
+                                    } else {
+                                        conf.daemon_group = Some(String::from(value));
+                                    }
+                                }
+                                ("ipctcpport", Some(value)) => {
+                                    let val: u16 = value.parse().unwrap();
+                                    conf.ipc_tcp_port = (val != 0).then_some(val);
+                                }
+                                ("ipctcpbindaddress", Some(value)) => {
+                                    if let Ok(ip) = IpAddr::from_str(value) {
+                                        conf.ipc_tcp_bind_address = ip;
+                                    }
+                                }
+                                ("ipcvsockport", Some(value)) => {
+                                    let val: u32 = value.parse().unwrap();
+                                    conf.ipc_vsock_port = (val != 0).then_some(val);
+                                }
                                 _ => {}
                             }
                         }
@@ -511,6 +566,10 @@ impl DaemonConfig {
                 }
             }
         }
+        // LoggingFilename may follow LoggingMode
+        if let DaemonLoggingMode::File(path) = &mut conf.logging_mode {
+            *path = conf.logging_filename.clone();
+        }
 
         Ok(conf)
     }
@@ -541,6 +600,7 @@ mod tests {
         assert_eq!(config.logging_mode, DaemonLoggingMode::Stdout);
         assert_eq!(config.logging_filename, PathBuf::from("/tmp/dlt.log"));
         assert_eq!(config.timeout_on_send, Duration::from_secs(4));
+        assert_eq!(config.app_id_log_level_file, None);
         assert_eq!(config.ring_buffer_min_size, 500000);
         assert_eq!(config.ring_buffer_max_size, 10000000);
         assert_eq!(config.ring_buffer_step_size, 500000);
@@ -582,7 +642,7 @@ mod tests {
             Some(PathBuf::from("<absolute-path-to-file>"))
         );
         assert_eq!(config.send_timezone, 0);
-        assert!(config.offline_logstorage_max_devices);
+        assert_eq!(config.offline_logstorage_max_devices, 0);
         assert_eq!(
             config.offline_logstorage_dir_path,
             Some(PathBuf::from("/opt"))
@@ -595,5 +655,8 @@ mod tests {
         assert_eq!("225.0.0.37".parse(), Ok(config.udpmulticast_ipaddress));
         assert_eq!("160.48.199.97".parse(), Ok(config.bind_address));
         assert_eq!(config.udpmulticast_ipport, 3491);
+        assert_eq!(config.ipc_tcp_port, None);
+        assert_eq!("127.0.0.1".parse(), Ok(config.ipc_tcp_bind_address));
+        assert_eq!(config.ipc_vsock_port, None);
     }
 }
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use ini::configparser::ini::Ini;
+
+use crate::error::DltError;
+
+/// Section with the settings shared by all passive nodes
+const DLT_GATEWAY_GENERAL_SECTION: &str = "general";
+
+const DLT_GATEWAY_DEFAULT_INTERVAL: u32 = 1;
+const DLT_GATEWAY_DEFAULT_PORT: u16 = 3490;
+const DLT_GATEWAY_DEFAULT_TIMEOUT: u32 = 10;
+
+/// When the connection to a passive node is established
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConnectTrigger {
+    /// When the daemon starts
+    OnStartup,
+    /// When requested with a passive node connect control message
+    OnDemand,
+}
+
+/// One passive node section of a `dlt_gateway.conf`, e.g. `[PassiveNode1]`
+#[derive(Debug, PartialEq, Clone)]
+pub struct PassiveNodeConfig {
+    /// Name of the section
+    pub name: String,
+    pub ip_address: IpAddr,
+    pub port: u16,
+    /// ECU id of the messages of the passive node
+    pub ecu_id: String,
+    pub connect: ConnectTrigger,
+    /// Seconds to retry connecting before giving up
+    pub timeout: u32,
+    /// The passive daemon expects a serial header in front of every message
+    pub send_serial_header: bool,
+    /// Control messages sent once the connection is established
+    pub send_control: Vec<u32>,
+    /// Control messages sent periodically, with their interval in seconds
+    pub send_periodic_control: Vec<(u32, u32)>,
+}
+
+impl PassiveNodeConfig {
+    fn from_section(name: &str, section: &HashMap<String, Option<String>>) -> Result<Self, String> {
+        let value = |key: &str| -> Option<&str> {
+            section
+                .get(key)
+                .and_then(|value| value.as_deref())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let required = |key: &str| value(key).ok_or_else(|| format!("{key} missing"));
+
+        let ip_address = required("ipaddress")?;
+        let ip_address = ip_address
+            .parse()
+            .map_err(|_| format!("invalid IPaddress {ip_address}"))?;
+        let ecu_id = required("ecuid")?;
+        if ecu_id.len() > 4 {
+            return Err(format!("invalid EcuID {ecu_id}"));
+        }
+        let port = match value("port") {
+            Some(port) => port.parse().map_err(|_| format!("invalid Port {port}"))?,
+            None => DLT_GATEWAY_DEFAULT_PORT,
+        };
+        let connect = match value("connect") {
+            None | Some("OnStartup") => ConnectTrigger::OnStartup,
+            Some("OnDemand") => ConnectTrigger::OnDemand,
+            Some(connect) => return Err(format!("invalid Connect {connect}")),
+        };
+        let timeout = match value("timeout") {
+            Some(timeout) => timeout
+                .parse()
+                .map_err(|_| format!("invalid Timeout {timeout}"))?,
+            None => DLT_GATEWAY_DEFAULT_TIMEOUT,
+        };
+        let send_control = match value("sendcontrol") {
+            Some(ids) => ids
+                .split(',')
+                .map(|id| parse_service_id(id).ok_or(format!("invalid SendControl {id}")))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let send_periodic_control = match value("sendperiodiccontrol") {
+            Some(ids) => ids
+                .split(',')
+                .map(|entry| {
+                    parse_periodic_control(entry)
+                        .ok_or(format!("invalid SendPeriodicControl {entry}"))
+                })
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(PassiveNodeConfig {
+            name: name.to_owned(),
+            ip_address,
+            port,
+            ecu_id: ecu_id.to_owned(),
+            connect,
+            timeout,
+            send_serial_header: value("sendserialheader") == Some("1"),
+            send_control,
+            send_periodic_control,
+        })
+    }
+}
+
+/// Service ids are written in hex, e.g. `0x03`
+fn parse_service_id(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// `<service id>:<interval>`, e.g. `0x03:5`
+fn parse_periodic_control(value: &str) -> Option<(u32, u32)> {
+    let (service_id, interval) = value.split_once(':')?;
+    let interval = interval
+        .trim()
+        .parse()
+        .ok()
+        .filter(|interval| *interval > 0)?;
+    Some((parse_service_id(service_id)?, interval))
+}
+
+/// The passive nodes a daemon in gateway mode connects to
+#[derive(Debug, PartialEq)]
+pub struct GatewayConfig {
+    /// Seconds between connection attempts and periodic control messages
+    pub interval: u32,
+    pub passive_nodes: Vec<PassiveNodeConfig>,
+    /// Why passive nodes were ignored, for the caller to report
+    pub skipped: Vec<String>,
+}
+
+impl GatewayConfig {
+    pub fn from_file(path: &str) -> Result<Self, DltError> {
+        let mut config = Ini::new();
+        config.load(path).map_err(DltError::ConfigFileError)?;
+        Ok(Self::from_ini(&config))
+    }
+
+    pub fn from_string(content: &str) -> Result<Self, DltError> {
+        let mut config = Ini::new();
+        config
+            .read(content.to_owned())
+            .map_err(DltError::ConfigFileError)?;
+        Ok(Self::from_ini(&config))
+    }
+
+    /// Invalid passive nodes and nodes reusing an ECU id are skipped
+    fn from_ini(config: &Ini) -> Self {
+        let interval = config
+            .get(DLT_GATEWAY_GENERAL_SECTION, "interval")
+            .and_then(|interval| interval.trim().parse().ok())
+            .filter(|interval| *interval > 0)
+            .unwrap_or(DLT_GATEWAY_DEFAULT_INTERVAL);
+
+        let mut sections: Vec<_> = config
+            .get_map_ref()
+            .iter()
+            .filter(|(name, _)| name.as_str() != DLT_GATEWAY_GENERAL_SECTION)
+            .collect();
+        sections.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut passive_nodes: Vec<PassiveNodeConfig> = Vec::new();
+        let mut skipped = Vec::new();
+        for (name, section) in sections {
+            match PassiveNodeConfig::from_section(name, section) {
+                Ok(node) if passive_nodes.iter().any(|n| n.ecu_id == node.ecu_id) => {
+                    skipped.push(format!(
+                        "Passive node {name} ignored: EcuID {} used twice",
+                        node.ecu_id
+                    ))
+                }
+                Ok(node) => passive_nodes.push(node),
+                Err(e) => skipped.push(format!("Passive node {name} ignored: {e}")),
+            }
+        }
+        GatewayConfig {
+            interval,
+            passive_nodes,
+            skipped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+[General]
+Interval=2
+
+[PassiveNode1]
+IPaddress=192.168.2.35
+Port=3491
+EcuID=ECU2
+Connect=OnStartup
+Timeout=5
+SendControl=0x03,0x13
+SendPeriodicControl=0x04:10
+
+[PassiveNode2]
+IPaddress=192.168.2.36
+EcuID=ECU3
+Connect=OnDemand
+SendSerialHeader=1
+
+[PassiveNode3]
+IPaddress=192.168.2.37
+EcuID=ECU2
+
+[PassiveNode4]
+IPaddress=localhost
+EcuID=ECU4
+";
+
+    #[test]
+    fn parse_passive_nodes() {
+        let config = GatewayConfig::from_string(CONFIG).unwrap();
+        assert_eq!(config.interval, 2);
+        // the third node reuses an ECU id, the fourth has no valid address
+        assert_eq!(config.passive_nodes.len(), 2);
+        assert_eq!(config.skipped.len(), 2);
+        assert_eq!(
+            config.skipped[0],
+            "Passive node passivenode3 ignored: EcuID ECU2 used twice"
+        );
+        assert_eq!(
+            config.passive_nodes[0],
+            PassiveNodeConfig {
+                name: "passivenode1".to_owned(),
+                ip_address: "192.168.2.35".parse().unwrap(),
+                port: 3491,
+                ecu_id: "ECU2".to_owned(),
+                connect: ConnectTrigger::OnStartup,
+                timeout: 5,
+                send_serial_header: false,
+                send_control: vec![0x03, 0x13],
+                send_periodic_control: vec![(0x04, 10)],
+            }
+        );
+        let node = &config.passive_nodes[1];
+        assert_eq!(node.port, 3490);
+        assert_eq!(node.connect, ConnectTrigger::OnDemand);
+        assert_eq!(node.timeout, 10);
+        assert!(node.send_serial_header);
+        assert!(node.send_control.is_empty());
+    }
+}
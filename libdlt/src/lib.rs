@@ -1,2 +1,4 @@
 pub mod config;
 pub mod error;
+pub mod gateway;
+pub mod logstorage;
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use ini::configparser::ini::Ini;
+
+use crate::error::DltError;
+
+/// Name of the filter configuration file on a logstorage device
+pub const DLT_OFFLINE_LOGSTORAGE_CONFIG_FILE_NAME: &str = "dlt_logstorage.conf";
+
+/// Sections defining a filter start with this name, e.g. `[FILTER1]`
+const DLT_OFFLINE_LOGSTORAGE_FILTER_SECTION: &str = "filter";
+
+/// Value of `LogAppName` and `ContextName` that matches every id
+const DLT_OFFLINE_LOGSTORAGE_WILDCARD: &str = ".*";
+
+// Sync strategies of a filter, combined with `,` in the configuration
+pub const DLT_LOGSTORAGE_SYNC_ON_MSG: u32 = 0x01;
+pub const DLT_LOGSTORAGE_SYNC_ON_DAEMON_EXIT: u32 = 0x02;
+pub const DLT_LOGSTORAGE_SYNC_ON_DEMAND: u32 = 0x04;
+pub const DLT_LOGSTORAGE_SYNC_ON_DEVICE_DISCONNECT: u32 = 0x08;
+pub const DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE: u32 = 0x10;
+pub const DLT_LOGSTORAGE_SYNC_ON_FILE_SIZE: u32 = 0x20;
+
+/// One `[FILTER]` section of a `dlt_logstorage.conf`. Messages matching the
+/// filter are stored in the files named by it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LogstorageFilter {
+    /// Name of the section
+    pub name: String,
+    /// Application ids to store, empty if all are stored
+    pub app_ids: Vec<String>,
+    /// Context ids to store, empty if all are stored
+    pub context_ids: Vec<String>,
+    /// Highest log level stored
+    pub log_level: u8,
+    /// Base name of the log files
+    pub file_name: String,
+    /// Maximum size of one log file
+    pub file_size: u32,
+    /// Maximum number of log files
+    pub num_files: u32,
+    /// Only store messages of this ECU
+    pub ecu_id: Option<String>,
+    /// When cached messages are written, `DLT_LOGSTORAGE_SYNC_*` flags
+    pub sync_behavior: u32,
+    /// Cache size for `DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE`
+    pub specific_size: u32,
+}
+
+impl LogstorageFilter {
+    /// Returns true if a log message with these ids and log level is stored
+    pub fn matches(&self, ecu_id: &str, app_id: &str, context_id: &str, log_level: u8) -> bool {
+        let listed = |ids: &Vec<String>, id: &str| ids.is_empty() || ids.iter().any(|i| i == id);
+        log_level <= self.log_level
+            && listed(&self.app_ids, app_id)
+            && listed(&self.context_ids, context_id)
+            && self.ecu_id.as_deref().is_none_or(|ecu| ecu == ecu_id)
+    }
+
+    fn from_section(name: &str, section: &HashMap<String, Option<String>>) -> Result<Self, String> {
+        let value = |key: &str| -> Option<&str> {
+            section
+                .get(key)
+                .and_then(|value| value.as_deref())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let required = |key: &str| value(key).ok_or_else(|| format!("{key} missing"));
+        let number = |key: &str| -> Result<u32, String> {
+            required(key)?.parse().map_err(|_| format!("invalid {key}"))
+        };
+        let ids = |key: &str| -> Vec<String> {
+            match value(key) {
+                None | Some(DLT_OFFLINE_LOGSTORAGE_WILDCARD) => Vec::new(),
+                Some(ids) => ids.split(',').map(|id| id.trim().to_owned()).collect(),
+            }
+        };
+
+        if value("logappname").is_none() && value("contextname").is_none() {
+            return Err("LogAppName or ContextName required".to_owned());
+        }
+        let log_level = required("loglevel")?;
+        let log_level =
+            parse_log_level(log_level).ok_or(format!("invalid LogLevel {log_level}"))?;
+        let file_name = required("file")?;
+        if file_name.contains('/') {
+            return Err(format!("invalid File {file_name}"));
+        }
+        let num_files = number("nofiles")?;
+        if num_files == 0 {
+            return Err("NOFiles must not be 0".to_owned());
+        }
+        let sync_behavior = match value("syncbehavior") {
+            Some(sync_behavior) => parse_sync_behavior(sync_behavior)
+                .ok_or(format!("invalid SyncBehavior {sync_behavior}"))?,
+            None => DLT_LOGSTORAGE_SYNC_ON_MSG,
+        };
+        let specific_size = if sync_behavior & DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE != 0 {
+            number("specificsize")?
+        } else {
+            0
+        };
+
+        Ok(LogstorageFilter {
+            name: name.to_owned(),
+            app_ids: ids("logappname"),
+            context_ids: ids("contextname"),
+            log_level,
+            file_name: file_name.to_owned(),
+            file_size: number("filesize")?,
+            num_files,
+            ecu_id: value("ecuid").map(str::to_owned),
+            sync_behavior,
+            specific_size,
+        })
+    }
+}
+
+fn parse_log_level(value: &str) -> Option<u8> {
+    let log_level = match value {
+        "DLT_LOG_OFF" => 0,
+        "DLT_LOG_FATAL" => 1,
+        "DLT_LOG_ERROR" => 2,
+        "DLT_LOG_WARN" => 3,
+        "DLT_LOG_INFO" => 4,
+        "DLT_LOG_DEBUG" => 5,
+        "DLT_LOG_VERBOSE" => 6,
+        _ => return None,
+    };
+    Some(log_level)
+}
+
+fn parse_sync_behavior(value: &str) -> Option<u32> {
+    let mut sync_behavior = 0;
+    for strategy in value.split(',') {
+        sync_behavior |= match strategy.trim() {
+            "ON_MSG" => DLT_LOGSTORAGE_SYNC_ON_MSG,
+            "ON_DAEMON_EXIT" => DLT_LOGSTORAGE_SYNC_ON_DAEMON_EXIT,
+            "ON_DEMAND" => DLT_LOGSTORAGE_SYNC_ON_DEMAND,
+            "ON_DEVICE_DISCONNECT" => DLT_LOGSTORAGE_SYNC_ON_DEVICE_DISCONNECT,
+            "ON_SPECIFIC_SIZE" => DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE,
+            "ON_FILE_SIZE" => DLT_LOGSTORAGE_SYNC_ON_FILE_SIZE,
+            _ => return None,
+        };
+    }
+    // writing every message makes all other strategies pointless
+    if sync_behavior & DLT_LOGSTORAGE_SYNC_ON_MSG != 0 {
+        sync_behavior = DLT_LOGSTORAGE_SYNC_ON_MSG;
+    }
+    Some(sync_behavior)
+}
+
+/// The filters configured on a logstorage device
+#[derive(Debug, PartialEq)]
+pub struct LogstorageConfig {
+    pub filters: Vec<LogstorageFilter>,
+    /// Why filters were ignored, for the caller to report
+    pub skipped: Vec<String>,
+}
+
+impl LogstorageConfig {
+    pub fn from_file(path: &str) -> Result<Self, DltError> {
+        let mut config = Ini::new();
+        config.load(path).map_err(DltError::ConfigFileError)?;
+        Ok(Self::from_ini(&config))
+    }
+
+    pub fn from_string(content: &str) -> Result<Self, DltError> {
+        let mut config = Ini::new();
+        config
+            .read(content.to_owned())
+            .map_err(DltError::ConfigFileError)?;
+        Ok(Self::from_ini(&config))
+    }
+
+    /// Invalid filters are skipped, like the C daemon does
+    fn from_ini(config: &Ini) -> Self {
+        let mut sections: Vec<_> = config
+            .get_map_ref()
+            .iter()
+            .filter(|(name, _)| name.starts_with(DLT_OFFLINE_LOGSTORAGE_FILTER_SECTION))
+            .collect();
+        sections.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut filters = Vec::new();
+        let mut skipped = Vec::new();
+        for (name, section) in sections {
+            match LogstorageFilter::from_section(name, section) {
+                Ok(filter) => filters.push(filter),
+                Err(e) => skipped.push(format!("Logstorage filter {name} ignored: {e}")),
+            }
+        }
+        LogstorageConfig { filters, skipped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+[FILTER1]
+LogAppName=APP1
+ContextName=.*
+LogLevel=DLT_LOG_WARN
+File=app1
+FileSize=10000
+NOFiles=5
+
+[FILTER2]
+LogAppName=APP2,APP3
+ContextName=CON1
+LogLevel=DLT_LOG_VERBOSE
+File=app23
+FileSize=10000
+NOFiles=1
+EcuID=ECU2
+SyncBehavior=ON_DEMAND, ON_SPECIFIC_SIZE
+SpecificSize=5000
+
+[FILTER3]
+LogAppName=APP4
+LogLevel=DLT_LOG_ALL
+File=app4
+FileSize=10000
+NOFiles=1
+";
+
+    #[test]
+    fn parse_filters() {
+        let config = LogstorageConfig::from_string(CONFIG).unwrap();
+        // the third filter has an invalid log level
+        assert_eq!(config.filters.len(), 2);
+        assert_eq!(config.skipped.len(), 1);
+        assert!(config.skipped[0].starts_with("Logstorage filter filter3 ignored: "));
+        assert_eq!(
+            config.filters[0],
+            LogstorageFilter {
+                name: "filter1".to_owned(),
+                app_ids: vec!["APP1".to_owned()],
+                context_ids: Vec::new(),
+                log_level: 3,
+                file_name: "app1".to_owned(),
+                file_size: 10000,
+                num_files: 5,
+                ecu_id: None,
+                sync_behavior: DLT_LOGSTORAGE_SYNC_ON_MSG,
+                specific_size: 0,
+            }
+        );
+        assert_eq!(config.filters[1].app_ids, vec!["APP2", "APP3"]);
+        assert_eq!(config.filters[1].ecu_id.as_deref(), Some("ECU2"));
+        assert_eq!(
+            config.filters[1].sync_behavior,
+            DLT_LOGSTORAGE_SYNC_ON_DEMAND | DLT_LOGSTORAGE_SYNC_ON_SPECIFIC_SIZE
+        );
+        assert_eq!(config.filters[1].specific_size, 5000);
+
+        // a specific size is required
+        let config = LogstorageConfig::from_string(
+            "[FILTER1]\nLogAppName=APP1\nLogLevel=DLT_LOG_WARN\nFile=app1\nFileSize=100\n\
+             NOFiles=1\nSyncBehavior=ON_SPECIFIC_SIZE\n",
+        )
+        .unwrap();
+        assert!(config.filters.is_empty());
+    }
+
+    #[test]
+    fn match_messages() {
+        let config = LogstorageConfig::from_string(CONFIG).unwrap();
+        let filter = &config.filters[0];
+        assert!(filter.matches("ECU1", "APP1", "ANY", 3));
+        assert!(!filter.matches("ECU1", "APP1", "ANY", 4));
+        assert!(!filter.matches("ECU1", "APP2", "ANY", 1));
+
+        let filter = &config.filters[1];
+        assert!(filter.matches("ECU2", "APP3", "CON1", 6));
+        assert!(!filter.matches("ECU1", "APP3", "CON1", 6));
+        assert!(!filter.matches("ECU2", "APP3", "CON2", 6));
+    }
+}